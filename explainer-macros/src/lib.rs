@@ -0,0 +1,405 @@
+//! Compile-time checked queries for `explainer`.
+//!
+//! `query!` and `query_as!` analyse their SQL while the calling crate is compiled,
+//! against one of
+//!
+//! - `EXPLAINER_DATABASE`: path of a database file, opened read-only,
+//! - `EXPLAINER_SCHEMA`: schema scripts, or directories of `*.sql` scripts applied in
+//!   name order, separated like `PATH`,
+//! - `EXPLAINER_OFFLINE_DIR`: a directory of query metadata recorded by an earlier build.
+//!
+//! When `EXPLAINER_OFFLINE_DIR` is set together with a database or a schema, the
+//! metadata of every query is recorded there, so later builds can check the queries
+//! without a database. Relative paths are resolved against the directory of the
+//! calling crate's `Cargo.toml`.
+//!
+//! Each argument is checked against the type inferred for its parameter: an argument
+//! that can't bind it, or an `Option` for a parameter that can't be NULL, fails to
+//! compile. A bare `None` needs its type, as in `None::<&str>`. A query can't mix
+//! anonymous `?` parameters with named ones.
+//!
+//! Queries are checked again when the `EXPLAINER_*` variables or the schema scripts
+//! change. The expansion is an `explainer::query::Query`, so the calling crate needs
+//! `explainer` as a dependency.
+
+use std::{
+    cell::RefCell,
+    env,
+    ffi::CString,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use explainer::{
+    analyzer::Analyzer, error::ExplainerError, offline::OfflineStore, types::StatementInfo,
+};
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Expr, Ident, LitStr, Path as TypePath, Token,
+};
+
+/// Check a query and read its rows into an anonymous struct.
+///
+/// ```ignore
+/// let user = query!("SELECT id, name FROM users WHERE id = ?", id).fetch_one(&conn)?;
+/// println!("{}: {}", user.id, user.name);
+/// ```
+#[proc_macro]
+pub fn query(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as QueryInput);
+    expand(None, input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Check a query and read its rows into an existing struct, with one field named after
+/// each result column.
+///
+/// ```ignore
+/// let users: Vec<User> = query_as!(User, "SELECT id, name FROM users").fetch_all(&conn)?;
+/// ```
+#[proc_macro]
+pub fn query_as(input: TokenStream) -> TokenStream {
+    let QueryAsInput { record, query } = parse_macro_input!(input as QueryAsInput);
+    expand(Some(record), query)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct QueryInput {
+    sql: LitStr,
+    args: Vec<Expr>,
+}
+
+impl Parse for QueryInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let sql = input.parse()?;
+        let args = if input.is_empty() {
+            Vec::new()
+        } else {
+            input.parse::<Token![,]>()?;
+            Punctuated::<Expr, Token![,]>::parse_terminated(input)?
+                .into_iter()
+                .collect()
+        };
+        Ok(QueryInput { sql, args })
+    }
+}
+
+struct QueryAsInput {
+    record: TypePath,
+    query: QueryInput,
+}
+
+impl Parse for QueryAsInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let record = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let query = input.parse()?;
+        Ok(QueryAsInput { record, query })
+    }
+}
+
+fn expand(record: Option<TypePath>, input: QueryInput) -> syn::Result<TokenStream2> {
+    let sql = input.sql.value();
+    let (info, schema_files) = statement_info(&sql).map_err(|e| analysis_error(&input.sql, &e))?;
+
+    check_parameters(&info).map_err(|e| syn::Error::new(input.sql.span(), e))?;
+
+    if input.args.len() != info.input_length {
+        return Err(syn::Error::new(
+            input.sql.span(),
+            format!(
+                "expected {} argument{}, got {}",
+                info.input_length,
+                if info.input_length == 1 { "" } else { "s" },
+                input.args.len()
+            ),
+        ));
+    }
+
+    let mut fields = Vec::new();
+    let mut types = Vec::new();
+    for (i, (column, ty)) in info.columns.iter().zip(&info.output_types).enumerate() {
+        let field = field_name(&column.name).ok_or_else(|| {
+            syn::Error::new(
+                input.sql.span(),
+                format!(
+                    "column `{}` is not a valid field name, rename it with `AS`",
+                    column.name
+                ),
+            )
+        })?;
+        let rust_type = ty.and_then(|ty| ty.rust_type()).ok_or_else(|| {
+            syn::Error::new(
+                input.sql.span(),
+                format!(
+                    "can't infer the type of column {i} (`{}`), give it one with `CAST`",
+                    column.name
+                ),
+            )
+        })?;
+        fields.push(field);
+        types.push(syn::parse_str::<syn::Type>(&rust_type)?);
+    }
+
+    let args = input.args.iter().zip(&info.input_types).map(|(arg, ty)| {
+        let Some((rust_type, nullable)) = ty
+            .as_ref()
+            .and_then(|ty| Some((ty.datatype.rust_type()?, ty.nullable != Some(false))))
+        else {
+            return quote!(#arg);
+        };
+        let rust_type = syn::parse_str::<syn::Type>(rust_type).expect("rust_type is a type");
+        match nullable {
+            true => quote!(::explainer::query::nullable_arg::<#rust_type, _>(#arg)),
+            false => quote!(::explainer::query::not_null_arg::<#rust_type, _>(#arg)),
+        }
+    });
+    // a change to these runs the macro again
+    let tracked = schema_files
+        .iter()
+        .filter_map(|path| path.to_str())
+        .map(|path| {
+            quote!(
+                const _: &[u8] = ::core::include_bytes!(#path);
+            )
+        })
+        .chain(ENV_VARS.iter().map(|name| {
+            quote!(
+                const _: ::core::option::Option<&str> = ::core::option_env!(#name);
+            )
+        }));
+    let tracked = quote!(#(#tracked)*);
+
+    let indices = 0..fields.len();
+    let sql = &input.sql;
+    let query = |record: &TokenStream2| {
+        quote! {
+            ::explainer::query::Query::<#record>::new(
+                ::explainer::cstr!(#sql),
+                ::std::vec![#(::std::boxed::Box::new(#args)),*],
+                |row| #record {
+                    #(#fields: ::explainer::query::FromSql::from_sql(row, #indices),)*
+                },
+            )
+        }
+    };
+    Ok(match record {
+        Some(record) => {
+            let query = query(&quote!(#record));
+            quote! {{
+                #tracked
+                #query
+            }}
+        }
+        None => {
+            let query = query(&quote!(Record));
+            quote! {{
+                #tracked
+                #[derive(Debug, Clone, PartialEq)]
+                #[allow(non_snake_case)]
+                struct Record {
+                    #(pub #fields: #types,)*
+                }
+                #query
+            }}
+        }
+    })
+}
+
+/// Reject queries mixing anonymous `?` parameters with named ones.
+fn check_parameters(info: &StatementInfo) -> Result<(), String> {
+    let anonymous = info.parameters.iter().any(|p| p.name.is_none() && !p.gap);
+    let named = info.parameters.iter().find_map(|p| {
+        p.name
+            .as_deref()
+            .filter(|name| name.starts_with([':', '@', '$']))
+    });
+    match (anonymous, named) {
+        (true, Some(name)) => Err(format!(
+            "the query mixes anonymous `?` parameters with `{name}`, use one style"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Error of analysing the query `sql`, showing the token SQLite failed at.
+fn analysis_error(sql: &LitStr, e: &anyhow::Error) -> syn::Error {
+    let Some(error) = e.downcast_ref::<ExplainerError>() else {
+        return syn::Error::new(sql.span(), format!("{e:#}"));
+    };
+    let span = error
+        .offset()
+        .and_then(|offset| token_span(sql, offset))
+        .unwrap_or_else(|| sql.span());
+    syn::Error::new(span, error.render(&sql.value()))
+}
+
+/// Span of the character at byte `offset` of the SQL in the literal `sql`.
+///
+/// `None` when the literal has escapes, which move the SQL in the source, or when the
+/// compiler can't point inside literals.
+fn token_span(sql: &LitStr, offset: usize) -> Option<Span> {
+    let token = sql.token();
+    let source = token.to_string();
+    let value = sql.value();
+    let start = source.find('"')? + 1;
+    if source.get(start..start + value.len())? != value {
+        return None;
+    }
+    let len = value
+        .get(offset..)?
+        .chars()
+        .next()
+        .map_or(0, char::len_utf8);
+    token.subspan(start + offset..start + offset + len)
+}
+
+/// Field name for a result column, `None` if the name can't be an identifier.
+fn field_name(column: &str) -> Option<Ident> {
+    if let Ok(ident) = syn::parse_str::<Ident>(column) {
+        return Some(ident);
+    }
+    // keywords, `type` and `match` are common column names
+    syn::parse_str::<Ident>(&format!("r#{column}")).ok()
+}
+
+const ENV_VARS: [&str; 3] = [
+    "EXPLAINER_DATABASE",
+    "EXPLAINER_SCHEMA",
+    "EXPLAINER_OFFLINE_DIR",
+];
+
+thread_local! {
+    // Analyses are cached for every macro call in the crate being compiled, with the
+    // schema scripts they read
+    static SOURCE: RefCell<Option<(Source, Vec<PathBuf>)>> = const { RefCell::new(None) };
+}
+
+enum Source {
+    Analyzer(Analyzer, Option<OfflineStore>),
+    Offline(OfflineStore),
+}
+
+fn statement_info(sql: &str) -> anyhow::Result<(StatementInfo, Vec<PathBuf>)> {
+    SOURCE.with(|source| {
+        let mut source = source.borrow_mut();
+        let (source, schema_files) = match &mut *source {
+            Some(source) => source,
+            None => source.insert(Source::from_env()?),
+        };
+        let info = match source {
+            Source::Analyzer(analyzer, None) => analyzer.statement_info(sql)?,
+            Source::Analyzer(analyzer, Some(store)) => store.record(analyzer, sql)?,
+            Source::Offline(store) => store.load(sql)?.info,
+        };
+        Ok((info, schema_files.clone()))
+    })
+}
+
+impl Source {
+    fn from_env() -> anyhow::Result<(Source, Vec<PathBuf>)> {
+        let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap_or_default());
+        let path = |name| env::var_os(name).map(|path| root.join(path));
+
+        let offline = path("EXPLAINER_OFFLINE_DIR").map(OfflineStore::new);
+        let mut schema_files = Vec::new();
+        let analyzer = match (path("EXPLAINER_DATABASE"), env::var_os("EXPLAINER_SCHEMA")) {
+            (Some(_), Some(_)) => {
+                bail!("`EXPLAINER_DATABASE` and `EXPLAINER_SCHEMA` can't be used together")
+            }
+            (Some(database), None) => {
+                let database = CString::new(database.to_string_lossy().into_owned())?;
+                Analyzer::open(&database)?
+            }
+            (None, Some(paths)) => {
+                let mut schema = Vec::new();
+                for path in env::split_paths(&paths) {
+                    read_schema(&root.join(path), &mut schema, &mut schema_files)?;
+                }
+                Analyzer::with_schema(&schema)?
+            }
+            (None, None) => {
+                return match offline {
+                    Some(store) => Ok((Source::Offline(store), schema_files)),
+                    None => bail!(
+                        "set `EXPLAINER_DATABASE`, `EXPLAINER_SCHEMA` or `EXPLAINER_OFFLINE_DIR` \
+                         to check queries"
+                    ),
+                }
+            }
+        };
+        Ok((Source::Analyzer(analyzer, offline), schema_files))
+    }
+}
+
+fn read_schema(
+    path: &Path,
+    schema: &mut Vec<String>,
+    files: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    if path.is_dir() {
+        let mut scripts = std::fs::read_dir(path)
+            .with_context(|| format!("failed to read `{}`", path.display()))?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        scripts.retain(|script| script.extension().is_some_and(|ext| ext == "sql"));
+        scripts.sort();
+        for script in scripts {
+            read_schema(&script, schema, files)?;
+        }
+    } else {
+        schema.push(
+            std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read `{}`", path.display()))?,
+        );
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_parameters() -> anyhow::Result<()> {
+        let check = |sql| -> anyhow::Result<_> {
+            let info = explainer::get_statement_info_with_schema(&[""; 0], sql)?;
+            Ok(check_parameters(&info))
+        };
+        assert_eq!(check("SELECT ?, ?3")?, Ok(()));
+        assert_eq!(check("SELECT :a, @b, :a")?, Ok(()));
+        assert_eq!(
+            check("SELECT ?, :a")?,
+            Err("the query mixes anonymous `?` parameters with `:a`, use one style".into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_schema() -> anyhow::Result<()> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+        let (mut schema, mut files) = (Vec::new(), Vec::new());
+        read_schema(&dir, &mut schema, &mut files)?;
+        assert_eq!(files, vec![dir.join("schema.sql")]);
+        assert!(schema[0].starts_with("CREATE TABLE users"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_field_name() {
+        assert_eq!(field_name("id").unwrap().to_string(), "id");
+        assert_eq!(field_name("userName").unwrap().to_string(), "userName");
+        assert_eq!(field_name("type").unwrap().to_string(), "r#type");
+        assert!(field_name("count(*)").is_none());
+        assert!(field_name("1").is_none());
+        assert!(field_name("self").is_none());
+    }
+}
@@ -0,0 +1,68 @@
+use std::ffi::CString;
+
+use explainer::{cstr, ffi::connection::Connection};
+use explainer_macros::{query, query_as};
+
+fn connection() -> anyhow::Result<Connection> {
+    let conn = Connection::establish(cstr!(":memory:"))?;
+    conn.exec_batch(&CString::new(include_str!("schema.sql"))?)?;
+    Ok(conn)
+}
+
+#[derive(Debug, PartialEq)]
+struct User {
+    id: i64,
+    name: String,
+    email: Option<String>,
+}
+
+#[test]
+fn test_query() -> anyhow::Result<()> {
+    let conn = connection()?;
+    let inserted = query!(
+        "INSERT INTO users (name, email, karma) VALUES (?, ?, ?)",
+        "alice",
+        Some("alice@example.com"),
+        10i64
+    )
+    .execute(&conn)?;
+    assert_eq!(inserted, 1);
+    query!("INSERT INTO users (name) VALUES (:name)", "bob").execute(&conn)?;
+
+    let user = query!(
+        "SELECT id, name, email, karma, karma * 1.5 AS score FROM users WHERE name = ?",
+        "alice"
+    )
+    .fetch_one(&conn)?;
+    let id: i64 = user.id;
+    let name: String = user.name;
+    let email: Option<String> = user.email;
+    let karma: i64 = user.karma;
+    assert_eq!(
+        (id, name.as_str(), email.as_deref(), karma),
+        (1, "alice", Some("alice@example.com"), 10)
+    );
+    assert_eq!(user.score, 15.0);
+
+    let users =
+        query_as!(User, "SELECT id, name, email FROM users ORDER BY id").fetch_all(&conn)?;
+    assert_eq!(
+        users,
+        vec![
+            User {
+                id: 1,
+                name: "alice".to_string(),
+                email: Some("alice@example.com".to_string()),
+            },
+            User {
+                id: 2,
+                name: "bob".to_string(),
+                email: None,
+            },
+        ]
+    );
+
+    let missing = query!("SELECT name FROM users WHERE id = ?", 3).fetch_optional(&conn)?;
+    assert!(missing.is_none());
+    Ok(())
+}
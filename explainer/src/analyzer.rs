@@ -0,0 +1,682 @@
+use std::ffi::CStr;
+
+use crate::cstr;
+use crate::error::ExplainerError;
+use crate::explain::{self, ExplainLimits, RootBlockColumns};
+use crate::ffi::{
+    connection::{Authorizer, Connection, OpenOptions, Savepoint},
+    error::SqliteError,
+    statement::Statement,
+};
+use crate::functions::FunctionSignatures;
+use crate::types::{ColumnInfo, DataType, ParameterInfo, ScriptStatement, StatementInfo};
+use crate::utils;
+
+/// Analyses many statements against one database connection.
+///
+/// The column types of the schema are read once and cached until the schema
+/// changes, as seen by `PRAGMA schema_version`, or until [`Analyzer::invalidate`]
+/// is called.
+#[derive(Debug)]
+pub struct Analyzer {
+    conn: Connection,
+    schema: SchemaCache,
+    functions: FunctionSignatures,
+    limits: ExplainLimits,
+    safe: bool,
+}
+
+#[derive(Debug, Default)]
+struct SchemaCache {
+    // (main schema_version, temp schema_version) the columns were read at
+    version: Option<(i64, i64)>,
+    columns: RootBlockColumns,
+}
+
+impl SchemaCache {
+    fn columns(&mut self, conn: &Connection) -> Result<&RootBlockColumns, SqliteError> {
+        let version = schema_version(conn)?;
+        if self.version != Some(version) {
+            self.columns = explain::root_block_columns(conn)?;
+            self.version = Some(version);
+        }
+        Ok(&self.columns)
+    }
+
+    /// The schema was rolled back to `version`, forget columns read at another version,
+    /// which later changes can reach again with different tables.
+    fn rolled_back(&mut self, version: (i64, i64)) {
+        if self.version != Some(version) {
+            *self = SchemaCache::default();
+        }
+    }
+}
+
+fn schema_version(conn: &Connection) -> Result<(i64, i64), SqliteError> {
+    let version = |query| -> Result<i64, SqliteError> {
+        let versions = conn.load_all(query, |row| Ok(row.column_int64(0)))?;
+        Ok(versions.first().copied().unwrap_or_default())
+    };
+    Ok((
+        version(cstr!("PRAGMA main.schema_version"))?,
+        version(cstr!("PRAGMA temp.schema_version"))?,
+    ))
+}
+
+fn has_function(conn: &Connection, name: &str, argc: i32) -> Result<bool, SqliteError> {
+    let mut stmt = conn.prepare(cstr!(
+        "SELECT 1 FROM pragma_function_list WHERE name = ? AND narg = ?"
+    ))?;
+    stmt.bind_text(1, &name.to_ascii_lowercase())?;
+    stmt.bind_int(2, argc)?;
+    stmt.step()
+}
+
+/// Pragmas that only read, even when given an argument.
+const READ_PRAGMAS: &[&str] = &[
+    "foreign_key_check",
+    "foreign_key_list",
+    "index_info",
+    "index_list",
+    "index_xinfo",
+    "integrity_check",
+    "quick_check",
+    "table_info",
+    "table_list",
+    "table_xinfo",
+];
+
+// SQLite runs some pragmas, such as `PRAGMA recursive_triggers = ON`, as they are prepared
+fn deny_pragma_writes(action: i32, pragma: Option<&str>, argument: Option<&str>) -> bool {
+    action != libsqlite3_sys::SQLITE_PRAGMA
+        || argument.is_none()
+        || pragma.is_some_and(|pragma| READ_PRAGMAS.contains(&&*pragma.to_ascii_lowercase()))
+}
+
+impl Analyzer {
+    pub fn new(conn: Connection) -> Analyzer {
+        Analyzer {
+            conn,
+            schema: SchemaCache::default(),
+            functions: FunctionSignatures::default(),
+            limits: ExplainLimits::default(),
+            safe: false,
+        }
+    }
+
+    /// Analyse statements against the database at `db_path`, opened read-only.
+    ///
+    /// Fails if there is no database at `db_path`, rather than creating one. Use
+    /// [`OpenOptions`] and [`Analyzer::new`] to open it otherwise.
+    pub fn open(db_path: &CStr) -> Result<Analyzer, ExplainerError> {
+        let conn = OpenOptions::new().read_only(true).open(db_path)?;
+        Ok(Analyzer::new(conn))
+    }
+
+    /// Analyse statements against the schema created by running each of the
+    /// `schema` scripts, in order, on an empty in-memory database.
+    pub fn with_schema<S: AsRef<str>>(schema: &[S]) -> Result<Analyzer, ExplainerError> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        for script in schema {
+            conn.exec_batch(&utils::to_cstring(script.as_ref())?)?;
+        }
+        Ok(Analyzer::new(conn))
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Use the return types in `functions` for application-defined functions.
+    ///
+    /// Functions the connection doesn't have yet are declared on it, so statements using
+    /// them can be prepared. Calling a declared function fails.
+    pub fn set_functions(&mut self, functions: FunctionSignatures) -> Result<(), ExplainerError> {
+        for (name, argc, aggregate) in functions.iter() {
+            if !has_function(&self.conn, name, argc)? {
+                self.conn
+                    .declare_function(&utils::to_cstring(name)?, argc, aggregate)?;
+            }
+        }
+        self.functions = functions;
+        Ok(())
+    }
+
+    pub fn functions(&self) -> &FunctionSignatures {
+        &self.functions
+    }
+
+    /// Bound the paths followed through a statement, past them the analysis fails with
+    /// [`ExplainerError::AnalysisIncomplete`].
+    pub fn set_limits(&mut self, limits: ExplainLimits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &ExplainLimits {
+        &self.limits
+    }
+
+    /// Analyse every statement inside a `SAVEPOINT` that is rolled back afterwards, with
+    /// an authorizer that denies pragmas setting a value, since SQLite runs some of them
+    /// as they are prepared. Analysing such a pragma then fails.
+    ///
+    /// Statements are only prepared, never executed, so with this nothing an analysis
+    /// does can change the database, whatever the SQL. The authorizer set on the
+    /// connection is replaced for each analysis and restored after it, also when the
+    /// analysis panics. One set through [`Connection::as_ptr`] is removed.
+    pub fn set_safe(&mut self, safe: bool) {
+        self.safe = safe;
+    }
+
+    pub fn safe(&self) -> bool {
+        self.safe
+    }
+
+    /// Drop the cached schema, it is read again by the next analysis.
+    pub fn invalidate(&mut self) {
+        self.schema = SchemaCache::default();
+    }
+
+    pub fn statement_info(&mut self, sql: &str) -> Result<StatementInfo, ExplainerError> {
+        let safe_mode = SafeMode::enter(&self.conn, self.safe)?;
+        let info = utils::to_cstring(sql).and_then(|sql_c| {
+            let stmt = self
+                .conn
+                .prepare(&sql_c)
+                .map_err(|e| ExplainerError::prepare(e, Some))?;
+            statement_info(
+                &self.conn,
+                &mut self.schema,
+                &self.functions,
+                &self.limits,
+                &stmt,
+                sql,
+            )
+        });
+        if let Some(safe_mode) = safe_mode {
+            let version = safe_mode.version;
+            safe_mode.exit()?;
+            self.schema.rolled_back(version);
+        }
+        info
+    }
+
+    /// Get the [`StatementInfo`] of every statement in a script of `;` separated statements.
+    ///
+    /// `CREATE`, `DROP` and `ALTER` statements are run after they are analysed, inside a
+    /// savepoint rolled back at the end, so later statements see the schema they change,
+    /// as when running migrations. Other statements are only prepared. On a read-only
+    /// connection nothing is run, and statements see the schema as it is.
+    pub fn script_info(&mut self, sql: &str) -> Result<Vec<ScriptStatement>, ExplainerError> {
+        // the version the schema is back at once the script is rolled back
+        let version = schema_version(&self.conn)?;
+        let safe_mode = SafeMode::enter(&self.conn, self.safe)?;
+        let statements = script_statements(
+            &self.conn,
+            &mut self.schema,
+            &self.functions,
+            &self.limits,
+            sql,
+        );
+        let exited = safe_mode.map_or(Ok(()), SafeMode::exit);
+        self.schema.rolled_back(version);
+        exited?;
+        statements
+    }
+}
+
+fn script_statements(
+    conn: &Connection,
+    schema: &mut SchemaCache,
+    functions: &FunctionSignatures,
+    limits: &ExplainLimits,
+    sql: &str,
+) -> Result<Vec<ScriptStatement>, ExplainerError> {
+    let sql_c = utils::to_cstring(sql)?;
+    let bytes = sql_c.as_bytes_with_nul();
+
+    let savepoint = match conn.is_read_only() {
+        true => None,
+        false => Some(conn.savepoint("explainer_script")?),
+    };
+    let mut statements = Vec::new();
+    let mut offset = 0;
+    while offset < sql.len() {
+        let tail = CStr::from_bytes_with_nul(&bytes[offset..])
+            .map_err(|_| ExplainerError::InteriorNul(offset))?;
+        let (stmt, consumed) = conn
+            .prepare_with_tail(tail)
+            .map_err(|e| ExplainerError::prepare(e, |i| Some(offset + i)))?;
+        let end = offset + consumed;
+        if let Some(mut stmt) = stmt {
+            let start = offset + utils::skip_whitespace_and_comments(&sql[offset..end]);
+            let end = start + sql[start..end].trim_end().len();
+            statements.push(ScriptStatement {
+                span: start..end,
+                info: statement_info(conn, schema, functions, limits, &stmt, &sql[start..end])?,
+            });
+            if savepoint.is_some() && changes_schema(&sql[start..end]) {
+                while stmt.step()? {}
+            }
+        }
+        if consumed == 0 {
+            break;
+        }
+        offset = end;
+    }
+    if let Some(savepoint) = savepoint {
+        savepoint.rollback()?;
+    }
+    Ok(statements)
+}
+
+/// What safe mode changes on the connection for one analysis, the savepoint it runs in
+/// and the authorizer it replaces, both undone when dropped, also on a panic.
+struct SafeMode<'a> {
+    conn: &'a Connection,
+    savepoint: Option<Savepoint<'a>>,
+    // to restore
+    authorizer: Option<Authorizer>,
+    // schema version before the savepoint
+    version: (i64, i64),
+}
+
+impl<'a> SafeMode<'a> {
+    /// Enter safe mode on `conn` if `safe`, see [`Analyzer::set_safe`].
+    fn enter(conn: &'a Connection, safe: bool) -> Result<Option<SafeMode<'a>>, SqliteError> {
+        if !safe {
+            return Ok(None);
+        }
+        let mut safe_mode = SafeMode {
+            conn,
+            savepoint: None,
+            authorizer: conn.authorizer(),
+            version: schema_version(conn)?,
+        };
+        conn.set_authorizer(Some(deny_pragma_writes))?;
+        safe_mode.savepoint = Some(conn.savepoint("explainer_analysis")?);
+        Ok(Some(safe_mode))
+    }
+
+    /// Leave safe mode, reporting a failure to roll back that dropping ignores.
+    fn exit(mut self) -> Result<(), SqliteError> {
+        // statements of the analysis are finalized by now, so the rollback can't fail on them
+        self.savepoint.take().map_or(Ok(()), Savepoint::rollback)
+    }
+}
+
+impl Drop for SafeMode<'_> {
+    fn drop(&mut self) {
+        drop(self.savepoint.take());
+        let _ = self.conn.set_authorizer(self.authorizer);
+    }
+}
+
+/// Whether `sql` starts with `CREATE`, `DROP` or `ALTER`.
+fn changes_schema(sql: &str) -> bool {
+    let keyword = sql
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or_default();
+    ["CREATE", "DROP", "ALTER"]
+        .iter()
+        .any(|ddl| keyword.eq_ignore_ascii_case(ddl))
+}
+
+fn statement_info(
+    conn: &Connection,
+    schema: &mut SchemaCache,
+    functions: &FunctionSignatures,
+    limits: &ExplainLimits,
+    stmt: &Statement,
+    sql: &str,
+) -> Result<StatementInfo, ExplainerError> {
+    let read_only = stmt.read_only();
+    let parameter_count = stmt.bind_parameter_count();
+
+    let occurrences = utils::parameter_occurrences(sql);
+    let anonymous = utils::anonymous_parameters(sql);
+    let parameters = (1..=parameter_count)
+        .map(|index| {
+            let name = stmt.bind_parameter_name(index);
+            ParameterInfo {
+                index,
+                name: name.map(str::to_string),
+                repeated: name
+                    .is_some_and(|name| occurrences.get(&utils::parameter_key(name)) > Some(&1)),
+                gap: name.is_none() && !anonymous.contains(&index),
+            }
+        })
+        .collect();
+
+    // output types
+    let column_count = stmt.column_count();
+
+    // t1: get types from db directly
+    let mut column_types = (0..column_count)
+        .map(|i| stmt.column_database_type(i))
+        .collect::<Result<Vec<_>, _>>()?;
+    let columns = (0..column_count)
+        .map(|i| ColumnInfo {
+            name: stmt.column_name(i).unwrap_or_default().to_string(),
+            declared_type: stmt.column_decltype(i).map(str::to_string),
+            origin: stmt.column_origin(i),
+        })
+        .collect();
+
+    // t2: get types from explain
+    // the nullability of a table column depends on the query: a NOT NULL column is NULL
+    // on the outer side of a join, a WHERE clause can filter out NULLs
+    let mut input_types = vec![None; parameter_count];
+    if column_count > 0 || parameter_count > 0 {
+        let explanation =
+            explain::explain_with_columns(conn, schema.columns(conn)?, functions, limits, sql)?;
+        for (i, column_type) in column_types.iter_mut().enumerate() {
+            let explained = explanation.output_types.get(i);
+            match column_type {
+                None => *column_type = explained.cloned(),
+                Some(declared) => {
+                    // an INTEGER PRIMARY KEY is read as the rowid, which is 64-bit
+                    if let Some(DataType::BigInt) = explained.map(|ty| ty.datatype) {
+                        if declared.datatype == DataType::Int {
+                            declared.datatype = DataType::BigInt;
+                        }
+                    }
+                    if let Some(explained) = explained
+                        .filter(|ty| ty.datatype != DataType::Null && ty.nullable.is_some())
+                    {
+                        declared.nullable = explained.nullable;
+                    }
+                }
+            }
+        }
+        for (input_type, explained) in input_types.iter_mut().zip(explanation.input_types) {
+            *input_type = explained;
+        }
+    }
+
+    Ok(StatementInfo {
+        read_only,
+        input_length: parameter_count,
+        input_types,
+        parameters,
+        output_length: column_count,
+        output_types: column_types,
+        columns,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::error::ErrorCode;
+    use crate::types::ColumnType;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_schema_cache_invalidation() -> anyhow::Result<()> {
+        let mut analyzer = Analyzer::with_schema(&["CREATE TABLE t(a INTEGER NOT NULL)"])?;
+        let info = analyzer.statement_info("SELECT a + 1 FROM t")?;
+        assert_eq!(
+            info.output_types,
+            vec![Some(ColumnType {
+                datatype: DataType::Int,
+                nullable: Some(false),
+            })]
+        );
+
+        analyzer
+            .connection()
+            .exec_batch(cstr!("DROP TABLE t; CREATE TABLE t(a TEXT)"))?;
+        let info = analyzer.statement_info("SELECT a || 'x' FROM t")?;
+        assert_eq!(
+            info.output_types,
+            vec![Some(ColumnType {
+                datatype: DataType::Text,
+                nullable: Some(true),
+            })]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_cache_after_script() -> anyhow::Result<()> {
+        let mut analyzer = Analyzer::with_schema(&[""; 0])?;
+        analyzer.script_info("CREATE TABLE a(x TEXT NOT NULL); SELECT x FROM a")?;
+        // rolled back, the schema reaches the version it had with `a` again
+        let script = analyzer.script_info("CREATE TABLE b(y INTEGER); SELECT y || '', y FROM b")?;
+        assert_eq!(
+            script[1].info.output_types,
+            vec![
+                Some(ColumnType {
+                    datatype: DataType::Text,
+                    nullable: Some(true),
+                }),
+                Some(ColumnType {
+                    datatype: DataType::Int,
+                    nullable: Some(true),
+                }),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_functions() -> anyhow::Result<()> {
+        let mut analyzer = Analyzer::with_schema(&["CREATE TABLE t(a INTEGER NOT NULL, b TEXT)"])?;
+        assert!(analyzer.statement_info("SELECT slugify(b) FROM t").is_err());
+
+        let mut functions = FunctionSignatures::new();
+        functions
+            .scalar(
+                "SLUGIFY",
+                1,
+                ColumnType {
+                    datatype: DataType::Text,
+                    nullable: Some(false),
+                },
+            )
+            .aggregate_with("median", 1, |args| ColumnType {
+                datatype: DataType::Real,
+                nullable: args[0].nullable,
+            });
+        analyzer.set_functions(functions)?;
+
+        let info = analyzer.statement_info("SELECT slugify(b), median(a) FROM t GROUP BY b")?;
+        assert_eq!(
+            info.output_types,
+            vec![
+                Some(ColumnType {
+                    datatype: DataType::Text,
+                    nullable: Some(false),
+                }),
+                Some(ColumnType {
+                    datatype: DataType::Real,
+                    nullable: Some(false),
+                }),
+            ]
+        );
+        let info = analyzer.statement_info("SELECT median(a) OVER (ORDER BY a) FROM t")?;
+        assert_eq!(
+            info.output_types,
+            vec![Some(ColumnType {
+                datatype: DataType::Real,
+                nullable: Some(false),
+            })]
+        );
+
+        let error = analyzer
+            .connection()
+            .exec_batch(cstr!(
+                "INSERT INTO t VALUES (1, 'x'); SELECT slugify(b) FROM t"
+            ))
+            .unwrap_err();
+        assert!(error.to_string().contains("declared for analysis only"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_outer_join_nullability() -> anyhow::Result<()> {
+        let mut analyzer =
+            Analyzer::with_schema(
+                &["CREATE TABLE p(id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+             CREATE TABLE c(id INTEGER PRIMARY KEY, pid INTEGER NOT NULL, v TEXT NOT NULL)"],
+            )?;
+        let text = |nullable| {
+            Some(ColumnType {
+                datatype: DataType::Text,
+                nullable: Some(nullable),
+            })
+        };
+
+        let info =
+            analyzer.statement_info("SELECT p.name, c.v FROM p LEFT JOIN c ON c.pid = p.id")?;
+        assert_eq!(info.output_types, vec![text(false), text(true)]);
+        assert_eq!(info.columns[1].declared_type.as_deref(), Some("TEXT"));
+        let info = analyzer.statement_info("SELECT p.name, c.v FROM p JOIN c ON c.pid = p.id")?;
+        assert_eq!(info.output_types, vec![text(false), text(false)]);
+        let info = analyzer.statement_info("SELECT id, pid FROM c")?;
+        assert_eq!(
+            info.output_types
+                .iter()
+                .map(|ty| ty.map(|ty| ty.datatype))
+                .collect::<Vec<_>>(),
+            vec![Some(DataType::BigInt), Some(DataType::Int)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_where_not_null() -> anyhow::Result<()> {
+        let mut analyzer = Analyzer::with_schema(&["CREATE TABLE t(a INTEGER, b TEXT)"])?;
+        let info = analyzer.statement_info("SELECT a, b FROM t WHERE b IS NOT NULL")?;
+        assert_eq!(
+            info.output_types,
+            vec![
+                Some(ColumnType {
+                    datatype: DataType::Int,
+                    nullable: Some(true),
+                }),
+                Some(ColumnType {
+                    datatype: DataType::Text,
+                    nullable: Some(false),
+                }),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_limits() -> anyhow::Result<()> {
+        let mut analyzer = Analyzer::with_schema(&["CREATE TABLE t(a INTEGER, b TEXT)"])?;
+        analyzer.set_limits(ExplainLimits {
+            max_instructions: 1,
+            ..ExplainLimits::default()
+        });
+        assert!(matches!(
+            analyzer.statement_info("SELECT a FROM t"),
+            Err(ExplainerError::AnalysisIncomplete(_))
+        ));
+        analyzer.set_limits(ExplainLimits::default());
+        assert!(analyzer.statement_info("SELECT a FROM t").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_read_only() -> anyhow::Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("explainer-missing-{}.db", std::process::id()));
+        let c_path = CString::new(path.to_str().unwrap())?;
+        let error = Analyzer::open(&c_path).unwrap_err();
+        assert_eq!(error.code(), Some(ErrorCode::CannotOpen));
+        assert!(!path.exists());
+
+        Connection::establish(&c_path)?.exec_batch(cstr!("CREATE TABLE t(a INTEGER)"))?;
+        let mut analyzer = Analyzer::open(&c_path)?;
+        assert!(analyzer.statement_info("SELECT a FROM t").is_ok());
+        assert!(analyzer.statement_info("DROP TABLE t").is_ok());
+        let error = analyzer
+            .connection()
+            .exec_batch(cstr!("DROP TABLE t"))
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::ReadOnly);
+        drop(analyzer);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_safe() -> anyhow::Result<()> {
+        let recursive_triggers = |analyzer: &Analyzer| {
+            analyzer
+                .connection()
+                .load_all(cstr!("PRAGMA recursive_triggers"), |row| {
+                    anyhow::Ok(row.column_int(0))
+                })
+        };
+        let mut analyzer = Analyzer::with_schema(&["CREATE TABLE t(a INTEGER NOT NULL)"])?;
+        // preparing the pragma is enough to run it
+        analyzer.statement_info("PRAGMA recursive_triggers = ON")?;
+        assert_eq!(recursive_triggers(&analyzer)?, vec![1]);
+
+        let mut analyzer = Analyzer::with_schema(&["CREATE TABLE t(a INTEGER NOT NULL)"])?;
+        analyzer.set_safe(true);
+        let error = analyzer
+            .script_info("SELECT 1;\nPRAGMA recursive_triggers = ON;")
+            .unwrap_err();
+        assert_eq!(
+            error.code(),
+            Some(ErrorCode::AuthorizationForStatementDenied)
+        );
+        assert_eq!(recursive_triggers(&analyzer)?, vec![0]);
+
+        let info = analyzer.statement_info("INSERT INTO t VALUES (?) RETURNING a")?;
+        assert_eq!(
+            info.output_types,
+            vec![Some(ColumnType {
+                datatype: DataType::Int,
+                nullable: Some(false),
+            })]
+        );
+        for sql in [
+            "DROP TABLE t",
+            "PRAGMA table_info(t)",
+            "PRAGMA main.schema_version",
+            "COMMIT",
+        ] {
+            assert!(analyzer.statement_info(sql).is_ok(), "{sql}");
+        }
+        assert!(analyzer.statement_info("SELECT nope").is_err());
+
+        // no savepoint is left open, and the authorizer is removed
+        let conn = analyzer.connection();
+        conn.exec_batch(cstr!("BEGIN; PRAGMA recursive_triggers = ON; COMMIT"))?;
+        conn.exec_batch(cstr!("DROP TABLE t"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_safe_restores_connection() -> anyhow::Result<()> {
+        let mut analyzer = Analyzer::with_schema(&["CREATE TABLE t(a INTEGER NOT NULL)"])?;
+        let mut functions = FunctionSignatures::new();
+        functions.scalar_with("boom", 1, |_| panic!("boom"));
+        analyzer.set_functions(functions)?;
+        analyzer.set_safe(true);
+        analyzer
+            .connection()
+            .set_authorizer(Some(|action, _, _| action != libsqlite3_sys::SQLITE_DELETE))?;
+        analyzer.statement_info("SELECT a FROM t")?;
+        let analysis = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            analyzer.statement_info("SELECT boom(a) FROM t")
+        }));
+        assert!(analysis.is_err());
+
+        // the authorizer of the connection is back, and no savepoint is left open
+        let conn = analyzer.connection();
+        assert!(conn.authorizer().is_some());
+        assert!(conn.prepare(cstr!("DELETE FROM t")).is_err());
+        conn.exec_batch(cstr!("BEGIN; COMMIT"))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,545 @@
+//! Generate a Rust module of typed query functions from annotated SQL.
+//!
+//! Every statement is preceded by a `-- name:` comment giving the name of its function,
+//! optionally followed by what the function returns:
+//!
+//! ```sql
+//! -- name: get_user :one
+//! SELECT id, name FROM users WHERE id = :id;
+//!
+//! -- name: list_users :many
+//! SELECT id, name FROM users ORDER BY name;
+//!
+//! -- name: delete_user :exec
+//! DELETE FROM users WHERE id = :id;
+//! ```
+//!
+//! `:one` returns the first row, if any, `:many` all rows and `:exec` the number of
+//! changed rows. Without one, statements returning columns are `:many` and other
+//! statements are `:exec`. The generated functions take an
+//! [`ffi::connection::Connection`](crate::ffi::connection::Connection) and run the
+//! statement with [`query::Query`](crate::query::Query).
+//!
+//! From a build script:
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use explainer::{analyzer::Analyzer, codegen};
+//!
+//! let schema = std::fs::read_to_string("schema.sql")?;
+//! let mut analyzer = Analyzer::with_schema(&[schema])?;
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR")?).join("queries.rs");
+//! codegen::write_module(&mut analyzer, &["queries.sql"], &out)?;
+//! println!("cargo:rerun-if-changed=schema.sql");
+//! println!("cargo:rerun-if-changed=queries.sql");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter, Write as _},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    analyzer::Analyzer,
+    error::ExplainerError,
+    types::{ColumnType, StatementInfo},
+};
+
+const HEADER: &str = "// Generated by explainer::codegen, do not edit.\n";
+
+/// Why a module couldn't be generated.
+#[derive(Debug)]
+pub enum CodegenError {
+    /// Reading a SQL file or writing the module failed.
+    Io { path: PathBuf, error: io::Error },
+    /// A statement couldn't be analysed.
+    Analysis(ExplainerError),
+    /// The SQL can't become a module, for the reason given.
+    Invalid(String),
+    /// The error is in this file of [`generate_files`].
+    InFile {
+        path: PathBuf,
+        error: Box<CodegenError>,
+    },
+    /// The error is in the statement of this query.
+    InQuery {
+        name: String,
+        error: Box<CodegenError>,
+    },
+}
+
+impl Display for CodegenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CodegenError::Io { path, error } => {
+                write!(f, "failed to access `{}`: {error}", path.display())
+            }
+            CodegenError::Analysis(e) => e.fmt(f),
+            CodegenError::Invalid(message) => f.write_str(message),
+            CodegenError::InFile { path, error } => write!(f, "in `{}`: {error}", path.display()),
+            CodegenError::InQuery { name, error } => {
+                write!(f, "failed to generate query `{name}`: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+impl From<ExplainerError> for CodegenError {
+    fn from(e: ExplainerError) -> Self {
+        CodegenError::Analysis(e)
+    }
+}
+
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err(CodegenError::Invalid(format!($($arg)*)))
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Returns {
+    One,
+    Many,
+    Exec,
+}
+
+/// A statement and the name given to it with `-- name:`.
+#[derive(Debug)]
+struct NamedStatement<'a> {
+    name: &'a str,
+    returns: Option<Returns>,
+    sql: &'a str,
+    info: StatementInfo,
+}
+
+/// Generate a module with one function per statement of the annotated `sql` script.
+pub fn generate(analyzer: &mut Analyzer, sql: &str) -> Result<String, CodegenError> {
+    module(named_statements(analyzer, sql)?)
+}
+
+/// Like [`generate`], for the statements of every file in `files`.
+pub fn generate_files<P: AsRef<Path>>(
+    analyzer: &mut Analyzer,
+    files: &[P],
+) -> Result<String, CodegenError> {
+    let scripts = files
+        .iter()
+        .map(|file| {
+            let file = file.as_ref();
+            std::fs::read_to_string(file).map_err(|error| CodegenError::Io {
+                path: file.to_path_buf(),
+                error,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut statements = Vec::new();
+    for (file, script) in files.iter().zip(&scripts) {
+        statements.extend(named_statements(analyzer, script).map_err(|error| {
+            CodegenError::InFile {
+                path: file.as_ref().to_path_buf(),
+                error: Box::new(error),
+            }
+        })?);
+    }
+    module(statements)
+}
+
+fn module(statements: Vec<NamedStatement>) -> Result<String, CodegenError> {
+    let mut out = HEADER.to_string();
+    let mut names = HashSet::new();
+    for statement in statements {
+        if !names.insert(statement.name) {
+            bail!("query `{}` is defined more than once", statement.name);
+        }
+        out.push('\n');
+        statement_fn(&mut out, &statement).map_err(|error| CodegenError::InQuery {
+            name: statement.name.to_string(),
+            error: Box::new(error),
+        })?;
+    }
+    Ok(out)
+}
+
+/// Write the module generated from `files` to `out`, leaving `out` untouched if it
+/// would not change so that it doesn't trigger a rebuild.
+pub fn write_module<P: AsRef<Path>>(
+    analyzer: &mut Analyzer,
+    files: &[P],
+    out: &Path,
+) -> Result<(), CodegenError> {
+    let module = generate_files(analyzer, files)?;
+    if std::fs::read_to_string(out).ok().as_deref() != Some(&*module) {
+        std::fs::write(out, module).map_err(|error| CodegenError::Io {
+            path: out.to_path_buf(),
+            error,
+        })?;
+    }
+    Ok(())
+}
+
+fn named_statements<'a>(
+    analyzer: &mut Analyzer,
+    sql: &'a str,
+) -> Result<Vec<NamedStatement<'a>>, CodegenError> {
+    let mut statements = Vec::new();
+    let mut gap_start = 0;
+    for statement in analyzer.script_info(sql)? {
+        let text = &sql[statement.span.clone()];
+        let Some((name, returns)) = annotation(&sql[gap_start..statement.span.start])? else {
+            bail!("statement has no `-- name:` comment:\n{text}");
+        };
+        statements.push(NamedStatement {
+            name,
+            returns,
+            sql: text,
+            info: statement.info,
+        });
+        gap_start = statement.span.end;
+    }
+    Ok(statements)
+}
+
+/// Name and return kind of the last `-- name:` comment in `comments`.
+fn annotation(comments: &str) -> Result<Option<(&str, Option<Returns>)>, CodegenError> {
+    let Some(line) = comments
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("--"))
+        .filter_map(|line| line.trim().strip_prefix("name:"))
+        .next_back()
+    else {
+        return Ok(None);
+    };
+    let mut words = line.split_whitespace();
+    let Some(name) = words.next() else {
+        bail!("`-- name:` comment without a name");
+    };
+    if identifier(name).is_none() {
+        bail!("`{name}` is not a valid function name");
+    }
+    let returns = match words.next() {
+        None => None,
+        Some(":one") => Some(Returns::One),
+        Some(":many") => Some(Returns::Many),
+        Some(":exec") => Some(Returns::Exec),
+        Some(returns) => bail!("unknown return kind `{returns}` for `{name}`"),
+    };
+    Ok(Some((name, returns)))
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "abstract", "become", "box", "do", "final", "gen", "macro", "override", "priv", "try",
+    "typeof", "unsized", "virtual", "yield",
+];
+
+/// `name` as a Rust identifier, raw if it is a keyword, `None` if it can't be one.
+fn identifier(name: &str) -> Option<String> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+        && name != "_";
+    match name {
+        _ if !valid => None,
+        "self" | "Self" | "super" | "crate" => None,
+        _ if KEYWORDS.contains(&name) => Some(format!("r#{name}")),
+        _ => Some(name.to_string()),
+    }
+}
+
+fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase())
+                .into_iter()
+                .chain(chars)
+        })
+        .collect()
+}
+
+/// Rust type of a parameter, borrowed where the column type is owned.
+fn parameter_type(ty: Option<&ColumnType>) -> String {
+    let Some(rust_type) = ty.and_then(|ty| ty.datatype.rust_type()) else {
+        return "&dyn ::explainer::query::ToSql".to_string();
+    };
+    let rust_type = match rust_type {
+        "String" => "&str",
+        "Vec<u8>" => "&[u8]",
+        rust_type => rust_type,
+    };
+    match ty.and_then(|ty| ty.nullable) {
+        Some(true) => format!("Option<{rust_type}>"),
+        _ => rust_type.to_string(),
+    }
+}
+
+fn statement_fn(out: &mut String, statement: &NamedStatement) -> Result<(), CodegenError> {
+    let info = &statement.info;
+    let row = format!("{}Row", pascal_case(statement.name));
+    let returns = match statement.returns {
+        Some(returns) => returns,
+        None if info.output_length > 0 => Returns::Many,
+        None => Returns::Exec,
+    };
+    if returns != Returns::Exec && info.output_length == 0 {
+        bail!("statement returns no columns, use `:exec`");
+    }
+
+    let mut fields = Vec::new();
+    if returns != Returns::Exec {
+        for (i, (column, ty)) in info.columns.iter().zip(&info.output_types).enumerate() {
+            let Some(field) = identifier(&column.name) else {
+                bail!(
+                    "column `{}` is not a valid field name, rename it with `AS`",
+                    column.name
+                );
+            };
+            let Some(ty) = ty.and_then(|ty| ty.rust_type()) else {
+                bail!(
+                    "can't infer the type of column {i} (`{}`), give it one with `CAST`",
+                    column.name
+                );
+            };
+            if fields.iter().any(|(other, _)| *other == field) {
+                bail!(
+                    "more than one column is named `{}`, alias them with `AS`",
+                    column.name
+                );
+            }
+            fields.push((field, ty));
+        }
+        let _ = writeln!(out, "#[derive(Debug, Clone, PartialEq)]");
+        let _ = writeln!(out, "pub struct {row} {{");
+        for (field, ty) in &fields {
+            let _ = writeln!(out, "    pub {field}: {ty},");
+        }
+        let _ = writeln!(out, "}}\n");
+    }
+
+    // (argument, its type, the parameter as written), `None` for the gaps left by
+    // `?NNN`, bound to NULL
+    let mut parameters: Vec<Option<(String, String, &str)>> = Vec::new();
+    for (parameter, ty) in info.parameters.iter().zip(&info.input_types) {
+        if parameter.gap {
+            parameters.push(None);
+            continue;
+        }
+        let written = parameter.name.as_deref().unwrap_or("?");
+        let name = parameter
+            .name
+            .as_deref()
+            .and_then(|name| identifier(&name[1..]))
+            .filter(|name| !name.starts_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or_else(|| format!("p{}", parameter.index));
+        if name == "conn" {
+            bail!("parameter `{written}` can't be named `conn`, the argument of the connection");
+        }
+        if let Some((_, _, other)) = parameters.iter().flatten().find(|(n, ..)| *n == name) {
+            bail!(
+                "parameters `{other}` and `{written}` both become the argument `{name}`, \
+                 rename one"
+            );
+        }
+        parameters.push(Some((name, parameter_type(ty.as_ref()), written)));
+    }
+
+    let (result, fetch) = match returns {
+        Returns::One => (format!("Option<{row}>"), "fetch_optional"),
+        Returns::Many => (format!("Vec<{row}>"), "fetch_all"),
+        Returns::Exec => ("usize".to_string(), "execute"),
+    };
+    let _ = write!(
+        out,
+        "pub fn {}(conn: &::explainer::ffi::connection::Connection",
+        statement.name
+    );
+    for (name, ty, _) in parameters.iter().flatten() {
+        let _ = write!(out, ", {name}: {ty}");
+    }
+    let _ = writeln!(
+        out,
+        ") -> Result<{result}, ::explainer::ffi::error::SqliteError> {{"
+    );
+    let _ = writeln!(out, "    ::explainer::query::Query::new(");
+    let _ = writeln!(out, "        ::explainer::cstr!({:?}),", statement.sql);
+    let _ = write!(out, "        vec![");
+    for (i, parameter) in parameters.iter().enumerate() {
+        let value = match parameter {
+            Some((name, ..)) => name,
+            None => "None::<i64>",
+        };
+        let _ = write!(out, "{}Box::new({value})", if i > 0 { ", " } else { "" });
+    }
+    let _ = writeln!(out, "],");
+    if returns == Returns::Exec {
+        let _ = writeln!(out, "        |_| (),");
+    } else {
+        let _ = writeln!(out, "        |row| {row} {{");
+        for (i, (field, _)) in fields.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "            {field}: ::explainer::query::FromSql::from_sql(row, {i}),"
+            );
+        }
+        let _ = writeln!(out, "        }},");
+    }
+    let _ = writeln!(out, "    )");
+    let _ = writeln!(out, "    .{fetch}(conn)");
+    let _ = writeln!(out, "}}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str =
+        "CREATE TABLE users(id INTEGER PRIMARY KEY, name TEXT NOT NULL, type TEXT);";
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let mut analyzer = Analyzer::with_schema(&[SCHEMA])?;
+        let sql = "\
+-- name: get_user :one
+SELECT name, type FROM users WHERE id = :id;
+
+-- Users named `name`, in no order.
+-- name: find_users
+SELECT name FROM users WHERE name = ?;
+
+-- name: add_user
+INSERT INTO users(name, type) VALUES (:name, ?2);
+";
+        assert_eq!(
+            generate(&mut analyzer, sql)?,
+            r#"// Generated by explainer::codegen, do not edit.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetUserRow {
+    pub name: String,
+    pub r#type: Option<String>,
+}
+
+pub fn get_user(conn: &::explainer::ffi::connection::Connection, id: i64) -> Result<Option<GetUserRow>, ::explainer::ffi::error::SqliteError> {
+    ::explainer::query::Query::new(
+        ::explainer::cstr!("SELECT name, type FROM users WHERE id = :id;"),
+        vec![Box::new(id)],
+        |row| GetUserRow {
+            name: ::explainer::query::FromSql::from_sql(row, 0),
+            r#type: ::explainer::query::FromSql::from_sql(row, 1),
+        },
+    )
+    .fetch_optional(conn)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FindUsersRow {
+    pub name: String,
+}
+
+pub fn find_users(conn: &::explainer::ffi::connection::Connection, p1: &str) -> Result<Vec<FindUsersRow>, ::explainer::ffi::error::SqliteError> {
+    ::explainer::query::Query::new(
+        ::explainer::cstr!("SELECT name FROM users WHERE name = ?;"),
+        vec![Box::new(p1)],
+        |row| FindUsersRow {
+            name: ::explainer::query::FromSql::from_sql(row, 0),
+        },
+    )
+    .fetch_all(conn)
+}
+
+pub fn add_user(conn: &::explainer::ffi::connection::Connection, name: &str, p2: Option<&str>) -> Result<usize, ::explainer::ffi::error::SqliteError> {
+    ::explainer::query::Query::new(
+        ::explainer::cstr!("INSERT INTO users(name, type) VALUES (:name, ?2);"),
+        vec![Box::new(name), Box::new(p2)],
+        |_| (),
+    )
+    .execute(conn)
+}
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_errors() -> anyhow::Result<()> {
+        let mut analyzer = Analyzer::with_schema(&[SCHEMA])?;
+        let mut error = |sql| generate(&mut analyzer, sql).unwrap_err().to_string();
+        assert!(error("SELECT 1").contains("no `-- name:` comment"));
+        assert!(
+            error("-- name: a\nDELETE FROM users;\n-- name: a\nDELETE FROM users;")
+                .contains("more than once")
+        );
+        assert!(error("-- name: a :all\nSELECT 1").contains("unknown return kind"));
+        assert!(error("-- name: a-b\nSELECT 1").contains("not a valid function name"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_clashes() -> anyhow::Result<()> {
+        let mut analyzer = Analyzer::with_schema(&[SCHEMA])?;
+        let mut error = |sql| format!("{:#}", generate(&mut analyzer, sql).unwrap_err());
+        assert!(error("-- name: a\nSELECT u.id, v.id FROM users u, users v")
+            .ends_with("more than one column is named `id`, alias them with `AS`"));
+        assert!(
+            error("-- name: a\nDELETE FROM users WHERE id = :id OR id = @id")
+                .ends_with("parameters `:id` and `@id` both become the argument `id`, rename one")
+        );
+        assert!(
+            error("-- name: a\nDELETE FROM users WHERE id = :p2 OR id = ?")
+                .ends_with("parameters `:p2` and `?` both become the argument `p2`, rename one")
+        );
+        assert!(error("-- name: a\nDELETE FROM users WHERE id = :conn")
+            .ends_with("parameter `:conn` can't be named `conn`, the argument of the connection"));
+
+        // the gaps of `?NNN` are bound to NULL
+        let module = generate(
+            &mut analyzer,
+            "-- name: a\nDELETE FROM users WHERE id = ?2 OR name = ?",
+        )?;
+        assert!(module.contains("conn: &::explainer::ffi::connection::Connection, p2: "));
+        assert!(module.contains("vec![Box::new(None::<i64>), Box::new(p2), Box::new(p3)],"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_files() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("explainer-codegen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let (a, b) = (dir.join("a.sql"), dir.join("b.sql"));
+        // no `;` after the last statement of a file
+        std::fs::write(
+            &a,
+            "-- name: count_users :one\nSELECT count(*) AS n FROM users",
+        )?;
+        std::fs::write(&b, "-- name: delete_users\nDELETE FROM users;\n")?;
+        let mut analyzer = Analyzer::with_schema(&[SCHEMA])?;
+        let module = generate_files(&mut analyzer, &[&a, &b])?;
+        assert!(module.contains("pub fn count_users("));
+        assert!(module.contains("pub fn delete_users("));
+
+        std::fs::write(&b, "-- name: bad\nSELECT nope FROM users;\n")?;
+        let error = format!(
+            "{:#}",
+            generate_files(&mut analyzer, &[&a, &b]).unwrap_err()
+        );
+        assert!(
+            error.starts_with(&format!("in `{}`: ", b.display())),
+            "{error}"
+        );
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    fmt::{self, Display, Formatter, Write as _},
+    str::Utf8Error,
+};
+
+#[cfg(feature = "offline")]
+use crate::offline::OfflineError;
+use crate::{
+    explain::AnalysisIncomplete,
+    ffi::error::{self, ErrorCode, ExtendedCode, SqliteError},
+};
+
+/// Why a statement couldn't be analysed.
+#[derive(Debug)]
+pub enum ExplainerError {
+    /// SQLite rejected the SQL of the statement.
+    Prepare {
+        code: ErrorCode,
+        extended_code: ExtendedCode,
+        message: String,
+        /// Byte offset in the SQL of the token SQLite failed at, if it reports one.
+        offset: Option<usize>,
+    },
+    /// A declared column type that maps to no [`DataType`](crate::types::DataType).
+    UnknownDeclaredType(String),
+    /// Text read from SQLite isn't valid UTF-8.
+    InvalidUtf8(Utf8Error),
+    /// The SQL holds a NUL byte at this byte offset, SQLite would stop reading there.
+    InteriorNul(usize),
+    /// Following the branches of the statement went past the
+    /// [`ExplainLimits`](crate::explain::ExplainLimits).
+    AnalysisIncomplete(AnalysisIncomplete),
+    /// Any other failure of SQLite, opening the database or reading its schema.
+    Sqlite(SqliteError),
+    /// A query expected to return a row returned none, see
+    /// [`Query::fetch_one`](crate::query::Query::fetch_one).
+    NoRows,
+    /// Recorded query metadata couldn't be written or loaded.
+    #[cfg(feature = "offline")]
+    Offline(OfflineError),
+}
+
+impl ExplainerError {
+    /// The error of preparing a statement, `offset` maps an offset in the prepared SQL
+    /// to the SQL of the caller.
+    pub(crate) fn prepare(
+        error: SqliteError,
+        offset: impl FnOnce(usize) -> Option<usize>,
+    ) -> ExplainerError {
+        ExplainerError::Prepare {
+            code: error.code(),
+            extended_code: error.extended_code(),
+            message: error.message().to_string(),
+            offset: error.offset().and_then(offset),
+        }
+    }
+
+    /// Primary result code of a failure of SQLite.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            ExplainerError::Prepare { code, .. } => Some(*code),
+            ExplainerError::Sqlite(e) => Some(e.code()),
+            _ => None,
+        }
+    }
+
+    /// Extended result code of a failure of SQLite.
+    pub fn extended_code(&self) -> Option<ExtendedCode> {
+        match self {
+            ExplainerError::Prepare { extended_code, .. } => Some(*extended_code),
+            ExplainerError::Sqlite(e) => Some(e.extended_code()),
+            _ => None,
+        }
+    }
+
+    /// Byte offset in the SQL of the token the error is about, if SQLite reports one.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            ExplainerError::Prepare { offset, .. } => *offset,
+            ExplainerError::InteriorNul(offset) => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// Line and column in `sql`, the SQL that was analysed, of the token the error is about.
+    pub fn location(&self, sql: &str) -> Option<Location> {
+        self.offset().map(|offset| Location::new(sql, offset))
+    }
+
+    /// The error followed by the line of `sql` it is on, with a caret under the token
+    /// the error is about:
+    ///
+    /// ```text
+    /// near "FORM": syntax error (SQL error or missing database, code 1)
+    ///  --> 1:10
+    ///   |
+    /// 1 | SELECT * FORM t
+    ///   |          ^
+    /// ```
+    pub fn render(&self, sql: &str) -> String {
+        let mut out = self.to_string();
+        let Some(location) = self.location(sql) else {
+            return out;
+        };
+        let line_start = sql[..location.offset].rfind('\n').map_or(0, |i| i + 1);
+        let line = sql[line_start..].lines().next().unwrap_or_default();
+        let number = location.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // tabs before the token keep the caret under it
+        let indent: String = line
+            .chars()
+            .take(location.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let _ = write!(
+            out,
+            "\n{gutter}--> {}:{}\n{gutter} |\n{number} | {line}\n{gutter} | {indent}^",
+            location.line, location.column
+        );
+        out
+    }
+}
+
+/// A position in SQL text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    /// Byte offset.
+    pub offset: usize,
+    /// Line number, from 1.
+    pub line: usize,
+    /// Character in the line, from 1.
+    pub column: usize,
+}
+
+impl Location {
+    /// Location of the byte `offset` of `sql`, or of its end if `offset` is past it.
+    pub fn new(sql: &str, offset: usize) -> Location {
+        let mut offset = offset.min(sql.len());
+        while !sql.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &sql[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Location {
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl Display for ExplainerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ExplainerError::Prepare {
+                extended_code,
+                message,
+                ..
+            } => error::fmt_error(f, *extended_code, message),
+            ExplainerError::UnknownDeclaredType(name) => write!(f, "unknown type: `{name}`"),
+            ExplainerError::InvalidUtf8(e) => e.fmt(f),
+            ExplainerError::InteriorNul(offset) => {
+                write!(f, "SQL holds a NUL byte at offset {offset}")
+            }
+            ExplainerError::AnalysisIncomplete(e) => e.fmt(f),
+            ExplainerError::Sqlite(e) => e.fmt(f),
+            ExplainerError::NoRows => f.write_str("query returned no rows"),
+            #[cfg(feature = "offline")]
+            ExplainerError::Offline(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ExplainerError {}
+
+impl From<SqliteError> for ExplainerError {
+    fn from(e: SqliteError) -> Self {
+        ExplainerError::Sqlite(e)
+    }
+}
+
+impl From<Utf8Error> for ExplainerError {
+    fn from(e: Utf8Error) -> Self {
+        ExplainerError::InvalidUtf8(e)
+    }
+}
+
+#[cfg(feature = "offline")]
+impl From<OfflineError> for ExplainerError {
+    fn from(e: OfflineError) -> Self {
+        ExplainerError::Offline(e)
+    }
+}
+
+impl From<AnalysisIncomplete> for ExplainerError {
+    fn from(e: AnalysisIncomplete) -> Self {
+        ExplainerError::AnalysisIncomplete(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syntax_error(offset: usize) -> ExplainerError {
+        ExplainerError::Prepare {
+            code: ErrorCode::Unknown,
+            extended_code: ExtendedCode::new(libsqlite3_sys::SQLITE_ERROR),
+            message: "near \"FORM\": syntax error".to_string(),
+            offset: Some(offset),
+        }
+    }
+
+    #[test]
+    fn test_location() {
+        let sql = "SELECT é,\n\t* FORM t";
+        assert_eq!(
+            Location::new(sql, 0),
+            Location {
+                offset: 0,
+                line: 1,
+                column: 1,
+            }
+        );
+        assert_eq!(
+            Location::new(sql, 14),
+            Location {
+                offset: 14,
+                line: 2,
+                column: 4,
+            }
+        );
+        // inside a character, and past the end
+        assert_eq!(Location::new(sql, 8).column, 8);
+        assert_eq!(Location::new(sql, 100).offset, sql.len());
+        assert_eq!(syntax_error(14).location(sql), Some(Location::new(sql, 14)));
+        assert_eq!(
+            ExplainerError::UnknownDeclaredType("x".into()).location(sql),
+            None
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            syntax_error(9).render("SELECT * FORM t"),
+            "near \"FORM\": syntax error (SQL error or missing database, code 1)
+ --> 1:10
+  |
+1 | SELECT * FORM t
+  |          ^"
+        );
+        let sql = format!("{}SELECT\t* FORM t\n", "\n".repeat(9));
+        assert_eq!(
+            syntax_error(18).render(&sql),
+            "near \"FORM\": syntax error (SQL error or missing database, code 1)
+  --> 10:10
+   |
+10 | SELECT\t* FORM t
+   |       \t  ^"
+        );
+        assert_eq!(
+            ExplainerError::UnknownDeclaredType("x".into()).render("SELECT 1"),
+            "unknown type: `x`"
+        );
+    }
+}
@@ -1,11 +1,15 @@
 // taken from sqlx
 use crate::cstr;
-use crate::ffi::connection::Connection;
+use crate::error::ExplainerError;
+use crate::ffi::{connection::Connection, error::SqliteError, row::Row};
+use crate::functions::{self, FunctionSignatures};
 use crate::types::ColumnType;
 use crate::types::DataType;
-use anyhow::Error;
-use std::collections::HashMap;
-use std::ffi::CString;
+use crate::utils;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::hash::{Hash, Hasher};
 use std::str::from_utf8;
 
 // affinity
@@ -16,6 +20,10 @@ const _SQLITE_AFF_NUMERIC: u8 = 0x43; /* 'C' */
 const SQLITE_AFF_INTEGER: u8 = 0x44; /* 'D' */
 const SQLITE_AFF_REAL: u8 = 0x45; /* 'E' */
 
+// comparison flags in p5
+const SQLITE_JUMPIFNULL: i64 = 0x10;
+const SQLITE_NULLEQ: i64 = 0x80;
+
 // opcodes
 const OP_INIT: &str = "Init";
 const OP_GOTO: &str = "Goto";
@@ -66,11 +74,14 @@ const OP_SEEK_GE: &str = "SeekGE";
 const OP_SEEK_GT: &str = "SeekGT";
 const OP_SEEK_LE: &str = "SeekLE";
 const OP_SEEK_LT: &str = "SeekLT";
-const OP_SEEK_ROW_ID: &str = "SeekRowId";
+const OP_SEEK_ROW_ID: &str = "SeekRowid";
 const OP_SEEK_SCAN: &str = "SeekScan";
 const OP_SEQUENCE_TEST: &str = "SequenceTest";
 const OP_SORTER_NEXT: &str = "SorterNext";
 const OP_SORTER_SORT: &str = "SorterSort";
+const OP_SORTER_OPEN: &str = "SorterOpen";
+const OP_SORTER_INSERT: &str = "SorterInsert";
+const OP_SORTER_DATA: &str = "SorterData";
 const OP_V_FILTER: &str = "VFilter";
 const OP_V_NEXT: &str = "VNext";
 const OP_YIELD: &str = "Yield";
@@ -84,16 +95,24 @@ const OP_OPEN_READ: &str = "OpenRead";
 const OP_OPEN_WRITE: &str = "OpenWrite";
 const OP_OPEN_EPHEMERAL: &str = "OpenEphemeral";
 const OP_OPEN_AUTOINDEX: &str = "OpenAutoindex";
+const OP_OPEN_DUP: &str = "OpenDup";
 const OP_AGG_FINAL: &str = "AggFinal";
 const OP_AGG_STEP: &str = "AggStep";
+const OP_COLL_SEQ: &str = "CollSeq";
+const OP_AGG_VALUE: &str = "AggValue";
+const OP_AGG_INVERSE: &str = "AggInverse";
 const OP_FUNCTION: &str = "Function";
+const OP_PURE_FUNC: &str = "PureFunc";
 const OP_MOVE: &str = "Move";
 const OP_COPY: &str = "Copy";
 const OP_SCOPY: &str = "SCopy";
 const OP_NULL: &str = "Null";
 const OP_NULL_ROW: &str = "NullRow";
+const OP_BEGIN_SUBRTN: &str = "BeginSubrtn";
 const OP_INT_COPY: &str = "IntCopy";
+const OP_ADD_IMM: &str = "AddImm";
 const OP_CAST: &str = "Cast";
+const OP_REAL_AFFINITY: &str = "RealAffinity";
 const OP_STRING8: &str = "String8";
 const OP_INT64: &str = "Int64";
 const OP_INTEGER: &str = "Integer";
@@ -103,6 +122,7 @@ const OP_BLOB: &str = "Blob";
 const OP_VARIABLE: &str = "Variable";
 const OP_COUNT: &str = "Count";
 const OP_ROWID: &str = "Rowid";
+const OP_IDX_ROWID: &str = "IdxRowid";
 const OP_NEWROWID: &str = "NewRowid";
 const OP_OR: &str = "Or";
 const OP_AND: &str = "And";
@@ -118,6 +138,7 @@ const OP_REMAINDER: &str = "Remainder";
 const OP_CONCAT: &str = "Concat";
 const OP_RESULT_ROW: &str = "ResultRow";
 const OP_HALT: &str = "Halt";
+const OP_HALT_IF_NULL: &str = "HaltIfNull";
 
 impl Default for ColumnType {
     fn default() -> Self {
@@ -137,11 +158,13 @@ impl ColumnType {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum RegDataType {
     Single(ColumnType),
     Record(Vec<ColumnType>),
     Int(i64),
+    // Value of the bind parameter with the given index
+    Variable(i64),
 }
 
 impl RegDataType {
@@ -150,6 +173,7 @@ impl RegDataType {
             RegDataType::Single(d) => d.datatype,
             RegDataType::Record(_) => DataType::Null, //If we're trying to coerce to a regular Datatype, we can assume a Record is invalid for the context
             RegDataType::Int(_) => DataType::Int,
+            RegDataType::Variable(_) => DataType::Null,
         }
     }
     fn map_to_nullable(&self) -> Option<bool> {
@@ -157,6 +181,7 @@ impl RegDataType {
             RegDataType::Single(d) => d.nullable,
             RegDataType::Record(_) => None, //If we're trying to coerce to a regular Datatype, we can assume a Record is invalid for the context
             RegDataType::Int(_) => Some(false),
+            RegDataType::Variable(_) => Some(true),
         }
     }
     fn map_to_columntype(&self) -> ColumnType {
@@ -170,14 +195,73 @@ impl RegDataType {
                 datatype: DataType::Int,
                 nullable: Some(false),
             },
+            RegDataType::Variable(_) => ColumnType::null(),
+        }
+    }
+
+    /// A type of the values of both `self` and `other`.
+    fn join(&self, other: &RegDataType) -> RegDataType {
+        match (self, other) {
+            (a, b) if a == b => a.clone(),
+            (RegDataType::Record(a), RegDataType::Record(b)) => RegDataType::Record(
+                (0..a.len().max(b.len()))
+                    .map(|i| match (a.get(i), b.get(i)) {
+                        (Some(&a), Some(&b)) => join_column_types(a, b),
+                        (Some(&col), None) | (None, Some(&col)) => col,
+                        (None, None) => ColumnType::default(),
+                    })
+                    .collect(),
+            ),
+            (a, b) => RegDataType::Single(join_column_types(
+                a.map_to_columntype(),
+                b.map_to_columntype(),
+            )),
         }
     }
 }
 
+fn join_column_types(a: ColumnType, b: ColumnType) -> ColumnType {
+    // nothing is known of a register a path didn't write
+    if a == ColumnType::default() {
+        return b;
+    }
+    if b == ColumnType::default() {
+        return a;
+    }
+    ColumnType {
+        datatype: if a.datatype == DataType::Null {
+            b.datatype
+        } else {
+            a.datatype
+        },
+        nullable: match (a.nullable, b.nullable) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        },
+    }
+}
+
+fn join_records(
+    a: &HashMap<i64, ColumnType>,
+    b: &HashMap<i64, ColumnType>,
+) -> HashMap<i64, ColumnType> {
+    let mut record = a.clone();
+    for (colnum, &col) in b {
+        record
+            .entry(*colnum)
+            .and_modify(|joined| *joined = join_column_types(*joined, col))
+            .or_insert(col);
+    }
+    record
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 enum CursorDataType {
     Normal(HashMap<i64, ColumnType>),
     Pseudo(i64),
+    // Another cursor on the ephemeral table of the given cursor, opened by OpenDup
+    Dup(i64),
 }
 
 impl CursorDataType {
@@ -207,6 +291,7 @@ impl CursorDataType {
                 Some(RegDataType::Record(r)) => r.clone(),
                 _ => Vec::new(),
             },
+            Self::Dup(_) => Vec::new(),
         }
     }
 
@@ -220,8 +305,26 @@ impl CursorDataType {
                 Some(RegDataType::Record(r)) => (0..).zip(r.iter().copied()).collect(),
                 _ => HashMap::new(),
             },
+            Self::Dup(_) => HashMap::new(),
         }
     }
+
+    fn hash_into(&self, hasher: &mut DefaultHasher) {
+        match self {
+            Self::Normal(record) => {
+                0.hash(hasher);
+                sorted(record).hash(hasher);
+            }
+            Self::Pseudo(i) => (1, i).hash(hasher),
+            Self::Dup(i) => (2, i).hash(hasher),
+        }
+    }
+}
+
+fn sorted<K: Ord + Copy, V>(map: &HashMap<K, V>) -> Vec<(K, &V)> {
+    let mut entries: Vec<_> = map.iter().map(|(&k, v)| (k, v)).collect();
+    entries.sort_by_key(|&(k, _)| k);
+    entries
 }
 
 #[allow(clippy::wildcard_in_or_patterns)]
@@ -243,13 +346,18 @@ fn opcode_to_type(op: &str) -> DataType {
         OP_BLOB => DataType::Blob,
         OP_AND | OP_OR => DataType::Bool,
         OP_INT64 => DataType::BigInt,
-        OP_ROWID | OP_COUNT | OP_INTEGER => DataType::Int,
+        // rowids are 64-bit
+        OP_ROWID | OP_NEWROWID => DataType::BigInt,
+        OP_COUNT | OP_INTEGER => DataType::Int,
         OP_STRING8 => DataType::Text,
         OP_COLUMN | _ => DataType::Null,
     }
 }
 
-fn root_block_columns(conn: &Connection) -> Result<HashMap<i64, HashMap<i64, ColumnType>>, Error> {
+/// Column types of every table and index in the schema, keyed by root page and column number.
+pub(crate) type RootBlockColumns = HashMap<i64, HashMap<i64, ColumnType>>;
+
+pub(crate) fn root_block_columns(conn: &Connection) -> Result<RootBlockColumns, SqliteError> {
     let table_block_columns: Vec<(i64, i64, String, bool)> = conn.load_all(
         cstr!(
             "SELECT s.rootpage, col.cid as colnum, col.type, col.\"notnull\"
@@ -257,7 +365,7 @@ fn root_block_columns(conn: &Connection) -> Result<HashMap<i64, HashMap<i64, Col
          JOIN pragma_table_info(s.name) AS col
          WHERE s.type = 'table'"
         ),
-        |row| -> anyhow::Result<_> {
+        |row| -> Result<_, SqliteError> {
             Ok((
                 row.column_int64(0),
                 row.column_int64(1),
@@ -276,7 +384,7 @@ fn root_block_columns(conn: &Connection) -> Result<HashMap<i64, HashMap<i64, Col
            ON col.cid = idx.cid
            WHERE s.type = 'index'"
         ),
-        |row| -> anyhow::Result<_> {
+        |row| -> Result<_, SqliteError> {
             Ok((
                 row.column_int64(0),
                 row.column_int64(1),
@@ -285,7 +393,7 @@ fn root_block_columns(conn: &Connection) -> Result<HashMap<i64, HashMap<i64, Col
             ))
         },
     )?;
-    let mut row_info: HashMap<i64, HashMap<i64, ColumnType>> = HashMap::new();
+    let mut row_info: RootBlockColumns = HashMap::new();
     for (block, colnum, datatype, notnull) in table_block_columns {
         let row_info = row_info.entry(block).or_default();
         row_info.insert(
@@ -322,50 +430,618 @@ struct QueryState {
     pub program_i: usize,
     // Results published by the execution
     pub result: Option<Vec<(Option<DataType>, Option<bool>)>>,
+    // Bind parameters stored in records: record register -> (column, parameter)
+    pub record_inputs: HashMap<i64, Vec<(i64, i64)>>,
+    // Aggregate accumulators AGG_STEP ran on since they were reset
+    pub stepped: HashSet<i64>,
+    // Accumulators in `stepped` still over no rows on some of the paths joined into this one
+    pub partly_stepped: HashSet<i64>,
+    // Cursors NULL_ROW moved to a row of NULLs, for the outer side of a join
+    pub null_rows: HashSet<i64>,
+    // Cursors on a NULL row on some of the paths joined into this one
+    pub maybe_null_rows: HashSet<i64>,
+    // (cursor, column) of the current row a branch found not NULL
+    pub not_null_columns: HashSet<(i64, i64)>,
+    // Subroutines entered by GOSUB
+    pub calls: Vec<Call>,
 }
 
-// Opcode Reference: https://sqlite.org/opcode.html
-pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error> {
-    let root_block_cols = root_block_columns(conn)?;
-    let query = {
-        let s = format!("EXPLAIN {query}");
-        let mut bytes = s.into_bytes();
-        bytes.push(0);
-        CString::from_vec_with_nul(bytes)?
+/// A subroutine entered by GOSUB.
+#[derive(Debug, Clone, PartialEq)]
+struct Call {
+    /// Register holding the return address.
+    reg: i64,
+    entry: usize,
+    /// `visited` of the body before the call.
+    body: Vec<bool>,
+    /// GOSUB instructions it returns after, more than one once calls are joined.
+    sites: BTreeSet<usize>,
+}
+
+type Instruction = (i64, String, i64, i64, i64, Vec<u8>, i64, String);
+
+impl QueryState {
+    /// Cursor `i`, or for a cursor opened by OpenDup the cursor of its table.
+    fn cursor(&self, i: i64) -> Option<&CursorDataType> {
+        match self.p.get(&i)? {
+            CursorDataType::Dup(table) => self.p.get(table),
+            cursor => Some(cursor),
+        }
+    }
+
+    fn cursor_mut(&mut self, i: i64) -> Option<&mut CursorDataType> {
+        let i = match self.p.get(&i)? {
+            CursorDataType::Dup(table) => *table,
+            _ => i,
+        };
+        self.p.get_mut(&i)
+    }
+
+    /// Narrow r[reg] to not NULL, and the column it was read from by the COLUMN
+    /// instructions and tests just before this test.
+    fn narrow_not_null(&mut self, program: &[Instruction], reg: i64) {
+        if let Some(RegDataType::Single(ty)) = self.r.get_mut(&reg) {
+            ty.nullable = Some(false);
+        }
+        for &i in self.history.iter().rev().skip(1) {
+            let (_, ref opcode, p1, p2, p3, ..) = program[i];
+            match &**opcode {
+                OP_COLUMN if p3 == reg => {
+                    self.not_null_columns.insert((p1, p2));
+                    break;
+                }
+                // tests write no registers, REAL_AFFINITY keeps a NULL
+                OP_COLUMN | OP_REAL_AFFINITY | OP_IS_NULL | OP_NOT_NULL | OP_EQ | OP_NE | OP_LT
+                | OP_LE | OP_GT | OP_GE => {}
+                _ => break,
+            }
+        }
+    }
+
+    /// Give the NULL fields of `record`, inserted into the table of `cursor` from r[reg],
+    /// the declared types of their columns. So do the registers the record was made of,
+    /// which RETURNING reads the inserted row from.
+    fn declare_null_fields(
+        &mut self,
+        program: &[Instruction],
+        cursor: i64,
+        reg: i64,
+        record: &mut [ColumnType],
+    ) {
+        let Some(CursorDataType::Normal(columns)) = self.cursor(cursor) else {
+            return;
+        };
+        let declared: Vec<_> = (0..record.len() as i64)
+            .map(|i| columns.get(&i).map(|ty| ty.datatype))
+            .collect();
+        // the first register of the MAKE_RECORD that last wrote r[reg]
+        let first = self
+            .history
+            .iter()
+            .rev()
+            .map(|&i| &program[i])
+            .find(|(_, opcode, _, p2, p3, ..)| match &**opcode {
+                OP_MAKE_RECORD => *p3 == reg,
+                OP_ROW_DATA | OP_SORTER_DATA => *p2 == reg,
+                _ => false,
+            })
+            .filter(|(_, opcode, ..)| opcode == OP_MAKE_RECORD)
+            .map(|&(_, _, p1, ..)| p1);
+        for (i, (field, declared)) in (0..).zip(record.iter_mut().zip(declared)) {
+            let Some(datatype) = declared.filter(|&datatype| datatype != DataType::Null) else {
+                continue;
+            };
+            if field.datatype != DataType::Null {
+                continue;
+            }
+            field.datatype = datatype;
+            if let Some(RegDataType::Single(ty)) =
+                first.and_then(|first| self.r.get_mut(&(first + i)))
+            {
+                if ty.datatype == DataType::Null {
+                    ty.datatype = datatype;
+                }
+            }
+        }
+    }
+
+    /// Type of the aggregate with the accumulator r[acc], given its type `empty` over no rows.
+    fn aggregate_result(&self, acc: i64, empty: Option<ColumnType>) -> Option<ColumnType> {
+        let stepped = self
+            .r
+            .get(&acc)
+            .filter(|_| self.stepped.contains(&acc))
+            .map(|d| d.map_to_columntype());
+        match (stepped, empty) {
+            (Some(stepped), Some(empty)) if self.partly_stepped.contains(&acc) => {
+                Some(join_column_types(stepped, empty))
+            }
+            (Some(stepped), _) => Some(stepped),
+            (None, empty) => empty,
+        }
+    }
+
+    /// Whether `other` can be joined into this state: both are at the same instruction
+    /// of the same subroutine calls, the innermost one from the same call sites.
+    fn can_join(&self, other: &QueryState) -> bool {
+        self.program_i == other.program_i
+            && self.record_inputs == other.record_inputs
+            && self.calls.len() == other.calls.len()
+            && self
+                .calls
+                .iter()
+                .zip(&other.calls)
+                .all(|(a, b)| (a.reg, a.entry) == (b.reg, b.entry))
+            && self.calls.last().map(|call| &call.sites)
+                == other.calls.last().map(|call| &call.sites)
+    }
+
+    /// Widen this state to the types of both paths, to follow them once from here.
+    fn join(&mut self, other: &QueryState) {
+        for (visited, other) in self.visited.iter_mut().zip(&other.visited) {
+            *visited &= other;
+        }
+        // tests after the join can't tell which path loaded a register
+        self.history.clear();
+        // an accumulator stepped on one path only keeps the type of its rows, AGG_FINAL
+        // joins it with the result over no rows
+        let partly_stepped: HashSet<i64> = self
+            .stepped
+            .symmetric_difference(&other.stepped)
+            .copied()
+            .collect();
+        for (reg, ty) in &other.r {
+            if partly_stepped.contains(reg) && !other.stepped.contains(reg) {
+                continue;
+            }
+            if partly_stepped.contains(reg) && !self.stepped.contains(reg) {
+                self.r.insert(*reg, ty.clone());
+                continue;
+            }
+            self.r
+                .entry(*reg)
+                .and_modify(|joined| *joined = joined.join(ty))
+                .or_insert_with(|| ty.clone());
+        }
+        let maybe_null_rows: Vec<i64> = self
+            .null_rows
+            .symmetric_difference(&other.null_rows)
+            .copied()
+            .collect();
+        self.null_rows.retain(|i| other.null_rows.contains(i));
+        self.maybe_null_rows.extend(&other.maybe_null_rows);
+        self.maybe_null_rows.extend(maybe_null_rows);
+        self.stepped.extend(&other.stepped);
+        self.partly_stepped.extend(&other.partly_stepped);
+        self.partly_stepped.extend(partly_stepped);
+        for (i, cursor) in &other.p {
+            match (self.p.get_mut(i), cursor) {
+                (Some(CursorDataType::Normal(a)), CursorDataType::Normal(b)) => {
+                    *a = join_records(a, b);
+                }
+                (Some(_), _) => {}
+                (None, cursor) => {
+                    self.p.insert(*i, cursor.clone());
+                }
+            }
+        }
+        self.not_null_columns
+            .retain(|col| other.not_null_columns.contains(col));
+        // RETURN goes back to the call sites of both paths
+        for (call, other) in self.calls.iter_mut().zip(&other.calls) {
+            for (visited, other) in call.body.iter_mut().zip(&other.body) {
+                *visited &= other;
+            }
+            call.sites.extend(&other.sites);
+        }
+    }
+
+    /// Hash of the types this state runs with from its instruction on.
+    fn key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.program_i.hash(&mut hasher);
+        sorted(&self.r).hash(&mut hasher);
+        for (i, cursor) in sorted(&self.p) {
+            i.hash(&mut hasher);
+            cursor.hash_into(&mut hasher);
+        }
+        sorted(&self.record_inputs).hash(&mut hasher);
+        let mut sets: Vec<Vec<i64>> = vec![
+            self.stepped.iter().copied().collect(),
+            self.partly_stepped.iter().copied().collect(),
+            self.null_rows.iter().copied().collect(),
+            self.maybe_null_rows.iter().copied().collect(),
+        ];
+        for set in &mut sets {
+            set.sort_unstable();
+        }
+        sets.hash(&mut hasher);
+        let mut not_null_columns: Vec<_> = self.not_null_columns.iter().collect();
+        not_null_columns.sort_unstable();
+        not_null_columns.hash(&mut hasher);
+        for call in &self.calls {
+            (call.reg, call.entry, &call.sites).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+/// States left to explore, by the instruction they wait at. A state joins a state
+/// waiting at its instruction, rather than following the same instructions again.
+#[derive(Default)]
+struct Worklist {
+    states: BTreeMap<usize, Vec<QueryState>>,
+}
+
+impl Worklist {
+    fn push(&mut self, state: QueryState) {
+        if !self.join(&state) {
+            self.states.entry(state.program_i).or_default().push(state);
+        }
+    }
+
+    /// A state waiting at the first instruction.
+    fn pop(&mut self) -> Option<QueryState> {
+        let mut waiting = self.states.first_entry()?;
+        let state = waiting.get_mut().pop();
+        if waiting.get().is_empty() {
+            waiting.remove();
+        }
+        state
+    }
+
+    fn first(&self) -> Option<usize> {
+        self.states.keys().next().copied()
+    }
+
+    /// Join `state` into a state waiting at the same instruction, if there is one.
+    fn join(&mut self, state: &QueryState) -> bool {
+        let waiting = self
+            .states
+            .get_mut(&state.program_i)
+            .and_then(|waiting| waiting.iter_mut().rev().find(|w| w.can_join(state)));
+        match waiting {
+            Some(waiting) => {
+                waiting.join(state);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Bounds on the work [`explain`] does following the branches of a program, which can
+/// be exponential in the number of branches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExplainLimits {
+    /// Most states taken from the worklist over all paths, counting each branch followed.
+    pub max_states: usize,
+    /// Most instructions interpreted over all paths.
+    pub max_instructions: usize,
+}
+
+impl Default for ExplainLimits {
+    fn default() -> Self {
+        Self {
+            max_states: 100_000,
+            max_instructions: 10_000_000,
+        }
+    }
+}
+
+/// The analysis of a statement stopped at one of its [`ExplainLimits`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnalysisIncomplete {
+    pub states: usize,
+    pub instructions: usize,
+}
+
+impl Display for AnalysisIncomplete {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "analysis incomplete: stopped after {} paths and {} instructions",
+            self.states, self.instructions
+        )
+    }
+}
+
+impl std::error::Error for AnalysisIncomplete {}
+
+/// Registers a branch instruction finds not NULL when it jumps, and when it falls through.
+fn not_null_operands(opcode: &str, p1: i64, p3: i64, p5: i64) -> (Vec<i64>, Vec<i64>) {
+    match opcode {
+        OP_IS_NULL => (vec![], vec![p1]),
+        OP_NOT_NULL => (vec![p1], vec![]),
+        // a comparison with NULL jumps with SQLITE_JUMPIFNULL and falls through without
+        OP_EQ | OP_NE | OP_LT | OP_LE | OP_GT | OP_GE if p5 & SQLITE_NULLEQ == 0 => {
+            if p5 & SQLITE_JUMPIFNULL != 0 {
+                (vec![], vec![p1, p3])
+            } else {
+                (vec![p1, p3], vec![])
+            }
+        }
+        _ => (vec![], vec![]),
+    }
+}
+
+/// Types of the arguments of every aggregate, by accumulator register, as far as they are
+/// table columns loaded right before its `AGG_STEP`. Over no rows `AGG_STEP` never runs,
+/// yet the result type may depend on them, e.g. `sum()` of a REAL column.
+fn aggregate_arguments(
+    program: &[Instruction],
+    root_block_cols: &RootBlockColumns,
+) -> HashMap<i64, Vec<ColumnType>> {
+    let table_column = |cursor: i64, column: i64| {
+        program
+            .iter()
+            .find(|(_, opcode, p1, _, p3, ..)| {
+                (opcode == OP_OPEN_READ || opcode == OP_OPEN_WRITE) && *p1 == cursor && *p3 == 0
+            })
+            .and_then(|(_, _, _, root, ..)| root_block_cols.get(root)?.get(&column).copied())
     };
-    let program: Vec<(i64, String, i64, i64, i64, Vec<u8>)> =
-        conn.load_all(&query, |row| -> anyhow::Result<_> {
-            Ok((
-                row.column_int64(0),
-                row.column_text(1).to_string(),
-                row.column_int64(2),
-                row.column_int64(3),
-                row.column_int64(4),
-                row.column_blob(5).to_vec(),
-            ))
-        })?;
-    let program_size = program.len();
+    let mut arguments = HashMap::new();
+    for (i, (_, opcode, _, p2, p3, _, p5, _)) in program.iter().enumerate() {
+        if opcode != OP_AGG_STEP {
+            continue;
+        }
+        let types = (*p2..p2 + p5)
+            .map(|reg| {
+                // the last instruction before the step that writes the register
+                program[..i]
+                    .iter()
+                    .rev()
+                    .find(|(_, _, _, q2, q3, ..)| *q2 == reg || *q3 == reg)
+                    .filter(|(_, opcode, ..)| opcode == OP_COLUMN)
+                    .and_then(|&(_, _, cursor, column, ..)| table_column(cursor, column))
+                    .unwrap_or_default()
+            })
+            .collect();
+        arguments.insert(*p3, types);
+    }
+    arguments
+}
+
+/// Types found by interpreting the VDBE program of a statement.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Explanation {
+    /// Types of the result columns.
+    pub output_types: Vec<ColumnType>,
+    /// Types of the bind parameters, indexed by parameter index - 1.
+    pub input_types: Vec<Option<ColumnType>>,
+}
+
+fn infer_input_type(inputs: &mut HashMap<i64, ColumnType>, parameter: i64, ty: ColumnType) {
+    if matches!(ty.datatype, DataType::Null) {
+        return;
+    }
+    inputs.entry(parameter).or_insert(ty);
+}
+
+// Learn the types of bind parameters from how the instruction uses them.
+// Must run before the instruction updates `state`.
+fn infer_input_types(
+    state: &QueryState,
+    inputs: &mut HashMap<i64, ColumnType>,
+    opcode: &str,
+    p1: i64,
+    p2: i64,
+    p3: i64,
+    p4: &[u8],
+) {
+    let rowid = ColumnType {
+        datatype: DataType::BigInt,
+        nullable: None,
+    };
+    let cursor_columns = |cursor: i64| {
+        state
+            .cursor(cursor)
+            .map(|c| c.map_to_sparse_record(&state.r))
+            .unwrap_or_default()
+    };
+
+    match opcode {
+        OP_EQ | OP_NE | OP_LT | OP_LE | OP_GT | OP_GE => {
+            // compare r[p1] with r[p3]
+            match (state.r.get(&p1), state.r.get(&p3)) {
+                (Some(RegDataType::Variable(i)), Some(other))
+                | (Some(other), Some(RegDataType::Variable(i))) => {
+                    infer_input_type(inputs, *i, other.map_to_columntype());
+                }
+                _ => {}
+            }
+        }
 
-    let mut states = vec![QueryState {
+        OP_SEEK_GE | OP_SEEK_GT | OP_SEEK_LE | OP_SEEK_LT | OP_IDX_GE | OP_IDX_GT | OP_IDX_LE
+        | OP_IDX_LT => {
+            // compare key r[p3 .. p3 + p4] with the index of cursor p1
+            let key_len = from_utf8(p4)
+                .ok()
+                .and_then(|p4| p4.parse::<i64>().ok())
+                .unwrap_or(1);
+            let columns = cursor_columns(p1);
+            for (colnum, reg) in (p3..p3 + key_len).enumerate() {
+                if let (Some(RegDataType::Variable(i)), Some(col)) =
+                    (state.r.get(&reg), columns.get(&(colnum as i64)))
+                {
+                    infer_input_type(inputs, *i, *col);
+                }
+            }
+        }
+
+        OP_SEEK_ROW_ID | OP_NOT_EXISTS => {
+            // seek cursor p1 to rowid r[p3]
+            if let Some(RegDataType::Variable(i)) = state.r.get(&p3) {
+                infer_input_type(inputs, *i, rowid);
+            }
+        }
+
+        OP_MUST_BE_INT => {
+            if let Some(RegDataType::Variable(i)) = state.r.get(&p1) {
+                infer_input_type(inputs, *i, rowid);
+            }
+        }
+
+        OP_CAST => {
+            if let Some(RegDataType::Variable(i)) = state.r.get(&p1) {
+                infer_input_type(
+                    inputs,
+                    *i,
+                    ColumnType {
+                        datatype: affinity_to_type(p2 as u8),
+                        nullable: None,
+                    },
+                );
+            }
+        }
+
+        OP_INSERT | OP_IDX_INSERT | OP_SORTER_INSERT => {
+            // record r[p2] is stored into cursor p1, with rowid r[p3] for tables
+            if let Some(record_inputs) = state.record_inputs.get(&p2) {
+                let columns = cursor_columns(p1);
+                for (colnum, i) in record_inputs {
+                    if let Some(col) = columns.get(colnum) {
+                        infer_input_type(inputs, *i, *col);
+                    }
+                }
+            }
+            if opcode == OP_INSERT {
+                if let Some(RegDataType::Variable(i)) = state.r.get(&p3) {
+                    infer_input_type(inputs, *i, rowid);
+                }
+            }
+        }
+
+        _ => {}
+    }
+}
+
+// Opcode Reference: https://sqlite.org/opcode.html
+pub fn explain(conn: &Connection, query: &str) -> Result<Explanation, ExplainerError> {
+    explain_with_columns(
+        conn,
+        &root_block_columns(conn)?,
+        &FunctionSignatures::default(),
+        &ExplainLimits::default(),
+        query,
+    )
+}
+
+pub(crate) fn explain_with_columns(
+    conn: &Connection,
+    root_block_cols: &RootBlockColumns,
+    signatures: &FunctionSignatures,
+    limits: &ExplainLimits,
+    query: &str,
+) -> Result<Explanation, ExplainerError> {
+    const EXPLAIN: &str = "EXPLAIN ";
+    let query = utils::to_cstring(&format!("{EXPLAIN}{query}")).map_err(|e| match e {
+        ExplainerError::InteriorNul(offset) => ExplainerError::InteriorNul(offset - EXPLAIN.len()),
+        e => e,
+    })?;
+    let mut stmt = conn
+        .prepare(&query)
+        .map_err(|e| ExplainerError::prepare(e, |offset| offset.checked_sub(EXPLAIN.len())))?;
+    let mut program: Vec<Instruction> = Vec::new();
+    while stmt.step()? {
+        let row = Row::new(&stmt);
+        program.push((
+            row.column_int64(0),
+            row.column_text(1).to_string(),
+            row.column_int64(2),
+            row.column_int64(3),
+            row.column_int64(4),
+            row.column_blob(5).to_vec(),
+            row.column_int64(6),
+            row.column_text(7).to_string(),
+        ));
+    }
+    let program_size = program.len();
+    let aggregate_arguments = aggregate_arguments(&program, root_block_cols);
+    let input_length = program
+        .iter()
+        .filter(|(_, opcode, ..)| opcode == OP_VARIABLE)
+        .map(|&(_, _, p1, ..)| p1)
+        .max()
+        .unwrap_or(0);
+
+    let mut states = Worklist::default();
+    states.push(QueryState {
         visited: vec![false; program_size],
         history: Vec::new(),
         r: HashMap::with_capacity(6),
         p: HashMap::with_capacity(6),
         program_i: 0,
         result: None,
-    }];
+        record_inputs: HashMap::new(),
+        stepped: HashSet::new(),
+        partly_stepped: HashSet::new(),
+        null_rows: HashSet::new(),
+        maybe_null_rows: HashSet::new(),
+        not_null_columns: HashSet::new(),
+        calls: Vec::new(),
+    });
+    let mut explored: HashMap<usize, Vec<QueryState>> = HashMap::new();
+    let mut state_count = 0;
+    let mut instruction_count = 0;
 
     let mut result_states = Vec::new();
+    let mut inputs = HashMap::new();
 
     while let Some(mut state) = states.pop() {
+        // from an instruction followed before, follow the join of both states, if it is
+        // any wider
+        let seen = explored.entry(state.program_i).or_default();
+        if let Some(before) = seen.iter_mut().find(|before| before.can_join(&state)) {
+            let mut joined = before.clone();
+            joined.join(&state);
+            if joined.key() == before.key() {
+                continue;
+            }
+            *before = joined.clone();
+            state = joined;
+        } else {
+            seen.push(state.clone());
+        }
+        state_count += 1;
         while state.program_i < program_size {
+            instruction_count += 1;
+            if state_count > limits.max_states || instruction_count > limits.max_instructions {
+                return Err(AnalysisIncomplete {
+                    states: state_count,
+                    instructions: instruction_count,
+                }
+                .into());
+            }
+            if states.join(&state) {
+                // a state waiting here follows both paths
+                break;
+            }
+            if states.first().is_some_and(|first| first < state.program_i) {
+                // follow the paths in program order, so they meet where they join
+                states.push(state);
+                break;
+            }
             if state.visited[state.program_i] {
+                // a loop reading rows from a coroutine or a rowset, or the NULL row of
+                // an outer join checked by IF_POS, has been followed once, carry on as if
+                // it had run out of rows, so rows it stored in ephemeral tables or
+                // sorters are seen by the code after the loop
+                let (_, ref opcode, _, p2, ..) = program[state.program_i];
+                if matches!(&**opcode, OP_YIELD | OP_ROW_SET_READ | OP_IF_POS)
+                    && p2 > 0
+                    && !state.visited[p2 as usize]
+                {
+                    state.program_i = p2 as usize;
+                    continue;
+                }
                 state.program_i += 1;
                 //avoid (infinite) loops by breaking if we ever hit the same instruction twice
                 break;
             }
-            let (_, ref opcode, p1, p2, p3, ref p4) = program[state.program_i];
+            let (_, ref opcode, p1, p2, p3, ref p4, p5, ref comment) = program[state.program_i];
             state.history.push(state.program_i);
+            infer_input_types(&state, &mut inputs, opcode, p1, p2, p3, p4);
 
             match &**opcode {
                 OP_INIT => {
@@ -383,25 +1059,98 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
                 }
 
                 OP_DECR_JUMP_ZERO | OP_ELSE_EQ | OP_EQ | OP_FILTER | OP_FK_IF_ZERO | OP_FOUND
-                | OP_GE | OP_GO_SUB | OP_GT | OP_IDX_GE | OP_IDX_GT | OP_IDX_LE | OP_IDX_LT
-                | OP_IF | OP_IF_NO_HOPE | OP_IF_NOT | OP_IF_NOT_OPEN | OP_IF_NOT_ZERO
-                | OP_IF_NULL_ROW | OP_IF_POS | OP_IF_SMALLER | OP_INCR_VACUUM | OP_IS_NULL
-                | OP_IS_NULL_OR_TYPE | OP_LE | OP_LAST | OP_LT | OP_MUST_BE_INT | OP_NE
-                | OP_NEXT | OP_NO_CONFLICT | OP_NOT_EXISTS | OP_NOT_NULL | OP_ONCE | OP_PREV
-                | OP_PROGRAM | OP_ROW_SET_READ | OP_ROW_SET_TEST | OP_SEEK_GE | OP_SEEK_GT
-                | OP_SEEK_LE | OP_SEEK_LT | OP_SEEK_ROW_ID | OP_SEEK_SCAN | OP_SEQUENCE_TEST
+                | OP_GE | OP_GT | OP_IDX_GE | OP_IDX_GT | OP_IDX_LE | OP_IDX_LT | OP_IF
+                | OP_IF_NO_HOPE | OP_IF_NOT | OP_IF_NOT_OPEN | OP_IF_NOT_ZERO | OP_IF_POS
+                | OP_IF_SMALLER | OP_INCR_VACUUM | OP_IS_NULL | OP_IS_NULL_OR_TYPE | OP_LE
+                | OP_LAST | OP_LT | OP_MUST_BE_INT | OP_NE | OP_NEXT | OP_NO_CONFLICT
+                | OP_NOT_EXISTS | OP_NOT_NULL | OP_ONCE | OP_PREV | OP_PROGRAM
+                | OP_ROW_SET_READ | OP_ROW_SET_TEST | OP_SEEK_GE | OP_SEEK_GT | OP_SEEK_LE
+                | OP_SEEK_LT | OP_SEEK_ROW_ID | OP_SEEK_SCAN | OP_SEQUENCE_TEST
                 | OP_SORTER_NEXT | OP_SORTER_SORT | OP_V_FILTER | OP_V_NEXT | OP_REWIND => {
                     // goto <p2> or next instruction (depending on actual values)
                     state.visited[state.program_i] = true;
 
-                    let mut branch_state = state.clone();
-                    branch_state.program_i = p2 as usize;
-                    states.push(branch_state);
+                    // a cursor that moves is no longer on a NULL row
+                    if matches!(
+                        &**opcode,
+                        OP_REWIND
+                            | OP_NEXT
+                            | OP_PREV
+                            | OP_LAST
+                            | OP_SEEK_GE
+                            | OP_SEEK_GT
+                            | OP_SEEK_LE
+                            | OP_SEEK_LT
+                            | OP_SEEK_ROW_ID
+                            | OP_NOT_EXISTS
+                            | OP_FOUND
+                            | OP_NO_CONFLICT
+                            | OP_SORTER_SORT
+                            | OP_SORTER_NEXT
+                    ) {
+                        state.null_rows.remove(&p1);
+                        state.maybe_null_rows.remove(&p1);
+                        state.not_null_columns.retain(|&(cursor, _)| cursor != p1);
+                    }
+
+                    // flags set by INTEGER decide a test, a counter IF_POS decrements may
+                    // run out in a later iteration
+                    let jumps = match (&**opcode, state.r.get(&p1)) {
+                        (OP_IF, Some(RegDataType::Int(v))) => Some(*v != 0),
+                        (OP_IF_NOT, Some(RegDataType::Int(v))) => Some(*v == 0),
+                        (OP_IF_POS, Some(RegDataType::Int(v))) if *v <= 0 || p3 == 0 => {
+                            Some(*v > 0)
+                        }
+                        _ => None,
+                    };
+                    if let Some(jumps) = jumps {
+                        state.program_i = if jumps {
+                            p2 as usize
+                        } else {
+                            state.program_i + 1
+                        };
+                        continue;
+                    }
+
+                    // the code of window functions has many jumps to the next instruction
+                    if p2 as usize != state.program_i + 1 {
+                        let (jump_not_null, next_not_null) = not_null_operands(opcode, p1, p3, p5);
+                        let mut branch_state = state.clone();
+                        for reg in jump_not_null {
+                            branch_state.narrow_not_null(&program, reg);
+                        }
+                        branch_state.program_i = p2 as usize;
+                        states.push(branch_state);
+                        for reg in next_not_null {
+                            state.narrow_not_null(&program, reg);
+                        }
+                    }
 
                     state.program_i += 1;
                     continue;
                 }
 
+                OP_IF_NULL_ROW => {
+                    // if cursor p1 is on a NULL row, r[p3] = NULL and goto <p2>
+                    state.visited[state.program_i] = true;
+                    if state.maybe_null_rows.remove(&p1) {
+                        let mut branch_state = state.clone();
+                        branch_state.null_rows.insert(p1);
+                        branch_state
+                            .r
+                            .insert(p3, RegDataType::Single(ColumnType::null()));
+                        branch_state.program_i = p2 as usize;
+                        states.push(branch_state);
+                        state.program_i += 1;
+                    } else if state.null_rows.contains(&p1) {
+                        state.r.insert(p3, RegDataType::Single(ColumnType::null()));
+                        state.program_i = p2 as usize;
+                    } else {
+                        state.program_i += 1;
+                    }
+                    continue;
+                }
+
                 OP_INIT_COROUTINE => {
                     // goto <p2> or next instruction (depending on actual values)
                     state.visited[state.program_i] = true;
@@ -419,8 +1168,7 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
                     // jump to p2 of the yield instruction pointed at by register p1
                     state.visited[state.program_i] = true;
                     if let Some(RegDataType::Int(yield_i)) = state.r.get(&p1) {
-                        if let Some((_, yield_op, _, yield_p2, _, _)) =
-                            program.get(*yield_i as usize)
+                        if let Some((_, yield_op, _, yield_p2, ..)) = program.get(*yield_i as usize)
                         {
                             if OP_YIELD == yield_op.as_str() {
                                 state.program_i = (*yield_p2) as usize;
@@ -437,13 +1185,60 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
                     }
                 }
 
+                OP_GO_SUB => {
+                    // r[p1] = address of this instruction, goto <p2>
+                    state.visited[state.program_i] = true;
+                    let entry = p2 as usize;
+                    if state.calls.iter().any(|call| call.entry == entry) {
+                        // a subroutine doesn't call itself
+                        break;
+                    }
+                    // the body runs again for every call site, up to the last RETURN p1
+                    let end = (entry..program_size)
+                        .rev()
+                        .find(|&i| program[i].1 == OP_RETURN && program[i].2 == p1)
+                        .unwrap_or(entry);
+                    let body = state.visited[entry..=end].to_vec();
+                    state.visited[entry..=end].fill(false);
+                    state.calls.push(Call {
+                        reg: p1,
+                        entry,
+                        body,
+                        sites: BTreeSet::from([state.program_i]),
+                    });
+                    state.r.insert(p1, RegDataType::Int(state.program_i as i64));
+                    state.program_i = p2 as usize;
+                    continue;
+                }
+
                 OP_RETURN => {
                     // jump to the instruction after the instruction pointed at by register p1
                     state.visited[state.program_i] = true;
-                    if let Some(RegDataType::Int(return_i)) = state.r.get(&p1) {
+                    if state.calls.last().is_some_and(|call| call.reg == p1) {
+                        let Some(call) = state.calls.pop() else { break };
+                        for (visited, before) in
+                            state.visited[call.entry..].iter_mut().zip(call.body)
+                        {
+                            *visited |= before;
+                        }
+                        state.r.remove(&p1);
+                        let mut sites = call.sites.into_iter();
+                        let Some(site) = sites.next() else { break };
+                        for site in sites {
+                            let mut branch_state = state.clone();
+                            branch_state.program_i = site + 1;
+                            states.push(branch_state);
+                        }
+                        state.program_i = site + 1;
+                        continue;
+                    } else if let Some(RegDataType::Int(return_i)) = state.r.get(&p1) {
                         state.program_i = (*return_i + 1) as usize;
                         state.r.remove(&p1);
                         continue;
+                    } else if p3 == 1 {
+                        // end of a subroutine started by BEGIN_SUBRTN and entered in-line
+                        state.program_i += 1;
+                        continue;
                     } else {
                         break;
                     }
@@ -458,7 +1253,7 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
                         //if yielding to a yield operation, go to the NEXT instruction after that instruction
                         if program
                             .get(*yield_i as usize)
-                            .map(|(_, yield_op, _, _, _, _)| yield_op.as_str())
+                            .map(|(_, yield_op, ..)| yield_op.as_str())
                             == Some(OP_YIELD)
                         {
                             state.program_i = (*yield_i + 1) as usize;
@@ -478,26 +1273,37 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
                     // goto one of <p1>, <p2>, or <p3> based on the result of a prior compare
                     state.visited[state.program_i] = true;
 
-                    let mut branch_state = state.clone();
-                    branch_state.program_i = p1 as usize;
-                    states.push(branch_state);
-
-                    let mut branch_state = state.clone();
-                    branch_state.program_i = p2 as usize;
-                    states.push(branch_state);
-
-                    let mut branch_state = state.clone();
-                    branch_state.program_i = p3 as usize;
-                    states.push(branch_state);
+                    // often two of them are the same instruction
+                    let mut targets = vec![p1, p2, p3];
+                    targets.sort_unstable();
+                    targets.dedup();
+                    let target = targets.pop().unwrap_or(p1);
+                    for target in targets {
+                        let mut branch_state = state.clone();
+                        branch_state.program_i = target as usize;
+                        states.push(branch_state);
+                    }
+                    state.program_i = target as usize;
+                    continue;
                 }
 
                 OP_COLUMN => {
                     //Get the row stored at p1, or NULL; get the column stored at p2, or NULL
-                    if let Some(record) = state.p.get(&p1).map(|c| c.map_to_sparse_record(&state.r))
+                    if state.null_rows.contains(&p1) {
+                        state.r.insert(p3, RegDataType::Single(ColumnType::null()));
+                    } else if let Some(record) =
+                        state.cursor(p1).map(|c| c.map_to_sparse_record(&state.r))
                     {
                         if let Some(col) = record.get(&p2) {
                             // insert into p3 the datatype of the col
-                            state.r.insert(p3, RegDataType::Single(*col));
+                            let mut col = *col;
+                            if state.maybe_null_rows.contains(&p1) {
+                                col.nullable = Some(true);
+                            }
+                            if state.not_null_columns.contains(&(p1, p2)) {
+                                col.nullable = Some(false);
+                            }
+                            state.r.insert(p3, RegDataType::Single(col));
                         } else {
                             state
                                 .r
@@ -510,9 +1316,10 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
                     }
                 }
 
-                OP_ROW_DATA => {
+                OP_ROW_DATA | OP_SORTER_DATA => {
                     //Get entire row from cursor p1, store it into register p2
-                    if let Some(record) = state.p.get(&p1) {
+                    state.record_inputs.remove(&p2);
+                    if let Some(record) = state.cursor(p1) {
                         let rowdata = record.map_to_dense_record(&state.r);
                         state.r.insert(p2, RegDataType::Record(rowdata));
                     } else {
@@ -533,13 +1340,28 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
                         );
                     }
                     state.r.insert(p3, RegDataType::Record(record));
+
+                    let record_inputs: Vec<_> = (p1..p1 + p2)
+                        .filter_map(|reg| match state.r.get(&reg) {
+                            Some(RegDataType::Variable(i)) => Some((reg - p1, *i)),
+                            _ => None,
+                        })
+                        .collect();
+                    if record_inputs.is_empty() {
+                        state.record_inputs.remove(&p3);
+                    } else {
+                        state.record_inputs.insert(p3, record_inputs);
+                    }
                 }
 
-                OP_INSERT | OP_IDX_INSERT => {
-                    if let Some(RegDataType::Record(record)) = state.r.get(&p2) {
-                        if let Some(CursorDataType::Normal(row)) = state.p.get_mut(&p1) {
+                OP_INSERT | OP_IDX_INSERT | OP_SORTER_INSERT => {
+                    if let Some(RegDataType::Record(mut record)) = state.r.get(&p2).cloned() {
+                        if opcode == OP_INSERT {
+                            state.declare_null_fields(&program, p1, p2, &mut record);
+                        }
+                        if let Some(CursorDataType::Normal(row)) = state.cursor_mut(p1) {
                             // Insert the record into wherever pointer p1 is
-                            *row = (0..).zip(record.iter().copied()).collect();
+                            *row = (0..).zip(record).collect();
                         }
                     }
                     //Noop if the register p2 isn't a record, or if pointer p1 does not exist
@@ -568,88 +1390,127 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
                     }
                 }
 
-                OP_OPEN_EPHEMERAL | OP_OPEN_AUTOINDEX => {
+                OP_OPEN_EPHEMERAL | OP_OPEN_AUTOINDEX | OP_SORTER_OPEN => {
                     //Create a new pointer which is referenced by p1
+                    // nothing is known about the columns until a record is inserted, a
+                    // path reading the table before that never sees a row
                     state.p.insert(
                         p1,
-                        CursorDataType::from_dense_record(&vec![ColumnType::null(); p2 as usize]),
+                        CursorDataType::from_dense_record(&vec![
+                            ColumnType::default();
+                            p2 as usize
+                        ]),
                     );
                 }
 
-                OP_VARIABLE => {
-                    // r[p2] = <value of variable>
-                    state.r.insert(p2, RegDataType::Single(ColumnType::null()));
+                OP_OPEN_DUP => {
+                    // Create a cursor p1 on the same ephemeral table as cursor p2
+                    let table = match state.p.get(&p2) {
+                        Some(CursorDataType::Dup(table)) => *table,
+                        _ => p2,
+                    };
+                    state.p.insert(p1, CursorDataType::Dup(table));
                 }
 
-                OP_FUNCTION => {
-                    // r[p1] = func( _ )
-                    // allow single_match for future use
-                    #[allow(clippy::single_match)]
-                    match from_utf8(p4)? {
-                        "last_insert_rowid(0)" => {
-                            // last_insert_rowid() -> INTEGER
-                            state.r.insert(
-                                p3,
-                                RegDataType::Single(ColumnType {
-                                    datatype: DataType::BigInt,
-                                    nullable: Some(false),
-                                }),
-                            );
-                        }
+                OP_VARIABLE => {
+                    // r[p2] = <value of variable p1>
+                    state.r.insert(p2, RegDataType::Variable(p1));
+                }
 
-                        _ => {
-                            // unkonwn function
-                        }
-                    }
+                OP_FUNCTION | OP_PURE_FUNC => {
+                    // r[p3] = func(r[p2 ..]), P4 doesn't show how many arguments are passed to
+                    // a variadic function, the comment does
+                    let passed = functions::argument_count(comment);
+                    let column_type =
+                        functions::return_type(signatures, from_utf8(p4)?, passed, |i| {
+                            state
+                                .r
+                                .get(&(p2 + i as i64))
+                                .map(|d| d.map_to_columntype())
+                                .unwrap_or_default()
+                        })
+                        // unknown function
+                        .unwrap_or_default();
+                    state.r.insert(p3, RegDataType::Single(column_type));
                 }
 
                 OP_NULL_ROW => {
-                    // all columns in cursor X are potentially nullable
-                    if let Some(CursorDataType::Normal(ref mut cursor)) = state.p.get_mut(&p1) {
-                        for ref mut col in cursor.values_mut() {
-                            col.nullable = Some(true);
-                        }
-                    }
-                    //else we don't know about the cursor
+                    // cursor p1 is on a row of NULLs until it moves
+                    state.null_rows.insert(p1);
+                    state.maybe_null_rows.remove(&p1);
                 }
 
                 OP_AGG_STEP => {
-                    //assume that AGG_FINAL will be called
+                    // r[p3] = AGG ( r[p2 ..] ), the result over the rows seen so far
                     let p4 = from_utf8(p4)?;
-
-                    if p4.starts_with("count(") {
-                        // count(_) -> INTEGER
-                        state.r.insert(
-                            p3,
-                            RegDataType::Single(ColumnType {
-                                datatype: DataType::BigInt,
-                                nullable: Some(false),
-                            }),
-                        );
+                    let column_type = {
+                        let arg = |i: usize| {
+                            state
+                                .r
+                                .get(&(p2 + i as i64))
+                                .map(|d| d.map_to_columntype())
+                                .unwrap_or_default()
+                        };
+                        signatures
+                            .return_type(p4, true, Some(p5 as usize), arg)
+                            .or_else(|| functions::aggregate_step_type(p4, arg))
+                    };
+                    if let Some(column_type) = column_type {
+                        state.r.insert(p3, RegDataType::Single(column_type));
                     } else if let Some(v) = state.r.get(&p2).cloned() {
-                        // r[p3] = AGG ( r[p2] )
+                        // unknown aggregate, assume it returns the type of its argument
                         state.r.insert(p3, v);
                     }
+                    state.stepped.insert(p3);
+                    state.partly_stepped.remove(&p3);
                 }
 
                 OP_AGG_FINAL => {
+                    // r[p1] = AGG result, from the accumulator in r[p1]
+                    // if AGG_STEP ran on this path the accumulator already has the type of
+                    // the result, otherwise the aggregate is over no rows
                     let p4 = from_utf8(p4)?;
+                    let arg = |i: usize| {
+                        aggregate_arguments
+                            .get(&p1)
+                            .and_then(|args| args.get(i).copied())
+                            .unwrap_or_default()
+                    };
+                    let passed = aggregate_arguments.get(&p1).map(Vec::len);
+                    let empty = signatures
+                        .return_type(p4, true, passed, arg)
+                        .or_else(|| functions::aggregate_empty_type(p4, arg));
+                    if state.r.contains_key(&p1) {
+                        if let Some(column_type) = state.aggregate_result(p1, empty) {
+                            state.r.insert(p1, RegDataType::Single(column_type));
+                        }
+                    }
+                }
 
-                    if p4.starts_with("count(") {
-                        // count(_) -> INTEGER
-                        state.r.insert(
-                            p1,
-                            RegDataType::Single(ColumnType {
-                                datatype: DataType::BigInt,
-                                nullable: Some(false),
-                            }),
-                        );
-                    } else if let Some(v) = state.r.get(&p2).cloned() {
-                        // r[p3] = AGG ( r[p2] )
-                        state.r.insert(p3, v);
+                OP_AGG_VALUE => {
+                    // r[p3] = current value of the window function with accumulator r[p1]
+                    // like AGG_FINAL, the frame is empty unless AGG_STEP ran on this path
+                    let p4 = from_utf8(p4)?;
+                    let arg = |i: usize| {
+                        aggregate_arguments
+                            .get(&p1)
+                            .and_then(|args| args.get(i).copied())
+                            .unwrap_or_default()
+                    };
+                    let passed = aggregate_arguments.get(&p1).map(Vec::len);
+                    let empty = signatures
+                        .return_type(p4, true, passed, arg)
+                        .or_else(|| functions::aggregate_empty_type(p4, arg));
+                    if let Some(column_type) = state.aggregate_result(p1, empty) {
+                        state.r.insert(p3, RegDataType::Single(column_type));
                     }
                 }
 
+                OP_AGG_INVERSE => {
+                    // remove r[p2 ..] from the frame of the window function with
+                    // accumulator r[p3], its type doesn't change
+                }
+
                 OP_CAST => {
                     // affinity(r[p1])
                     if let Some(v) = state.r.get_mut(&p1) {
@@ -661,18 +1522,56 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
                 }
 
                 OP_COPY | OP_MOVE | OP_SCOPY | OP_INT_COPY => {
-                    // r[p2] = r[p1]
-                    if let Some(v) = state.r.get(&p1).cloned() {
-                        state.r.insert(p2, v);
+                    // r[p2 .. p2 + n] = r[p1 .. p1 + n]
+                    let n = match &**opcode {
+                        OP_COPY => p3 + 1,
+                        OP_MOVE => p3,
+                        _ => 1,
+                    };
+                    for i in 0..n {
+                        if let Some(v) = state.r.get(&(p1 + i)).cloned() {
+                            state.r.insert(p2 + i, v);
+                        }
                     }
                 }
 
+                OP_ADD_IMM => {
+                    // r[p1] += p2, as an integer
+                    let value = match state.r.get(&p1) {
+                        Some(RegDataType::Int(value)) => RegDataType::Int(value + p2),
+                        _ => RegDataType::Single(ColumnType {
+                            datatype: DataType::Int,
+                            nullable: Some(false),
+                        }),
+                    };
+                    state.r.insert(p1, value);
+                }
+
                 OP_INTEGER => {
                     // r[p2] = p1
                     state.r.insert(p2, RegDataType::Int(p1));
                 }
 
-                OP_BLOB | OP_COUNT | OP_REAL | OP_STRING8 | OP_ROWID | OP_NEWROWID => {
+                OP_COLL_SEQ if p1 != 0 => {
+                    // r[p1] = 0, set to 1 by a min() or max() AGG_STEP that keeps its value,
+                    // which it can't for the first row
+                    state.r.insert(p1, RegDataType::Int(0));
+                }
+
+                OP_ROWID | OP_IDX_ROWID => {
+                    // r[p2] = rowid of cursor p1, NULL on a NULL row
+                    let column_type = if state.null_rows.contains(&p1) {
+                        ColumnType::null()
+                    } else {
+                        ColumnType {
+                            datatype: opcode_to_type(OP_ROWID),
+                            nullable: Some(state.maybe_null_rows.contains(&p1)),
+                        }
+                    };
+                    state.r.insert(p2, RegDataType::Single(column_type));
+                }
+
+                OP_BLOB | OP_COUNT | OP_REAL | OP_STRING8 | OP_NEWROWID => {
                     // r[p2] = <value of constant>
                     state.r.insert(
                         p2,
@@ -690,12 +1589,14 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
                     }
                 }
 
-                OP_NULL => {
+                OP_NULL | OP_BEGIN_SUBRTN => {
                     // r[p2..p3] = null
                     let idx_range = if p2 < p3 { p2..=p3 } else { p2..=p2 };
 
                     for idx in idx_range {
                         state.r.insert(idx, RegDataType::Single(ColumnType::null()));
+                        state.stepped.remove(&idx);
+                        state.partly_stepped.remove(&idx);
                     }
                 }
 
@@ -770,6 +1671,20 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
                     break;
                 }
 
+                OP_HALT_IF_NULL => {
+                    // NOT NULL constraint, r[p3] isn't null past this
+                    // bind parameters are kept, their type is learnt where they are stored
+                    if let Some(v @ (RegDataType::Single(_) | RegDataType::Int(_))) =
+                        state.r.get_mut(&p3)
+                    {
+                        let mut column_type = v.map_to_columntype();
+                        if column_type.nullable != Some(false) {
+                            column_type.nullable = Some(false);
+                            *v = RegDataType::Single(column_type);
+                        }
+                    }
+                }
+
                 _ => {
                     // ignore unsupported operations
                     // if we fail to find an r later, we just give up
@@ -808,21 +1723,38 @@ pub fn explain(conn: &Connection, query: &str) -> Result<Vec<ColumnType>, Error>
         }
     }
 
-    let output = output
+    let output_types = output
         .into_iter()
-        .zip(nullable.into_iter())
+        .zip(nullable)
         .map(|(datatype, nullable)| ColumnType {
             datatype: datatype.unwrap_or(DataType::Null),
             nullable,
         })
         .collect();
 
-    Ok(output)
+    let input_types = (1..=input_length)
+        .map(|i| inputs.get(&i).copied())
+        .collect();
+
+    Ok(Explanation {
+        output_types,
+        input_types,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    /// Data types and nullability of the result columns of `sql`.
+    fn output_types(conn: &Connection, sql: &str) -> anyhow::Result<Vec<(DataType, Option<bool>)>> {
+        Ok(explain(conn, sql)?
+            .output_types
+            .iter()
+            .map(|ty| (ty.datatype, ty.nullable))
+            .collect())
+    }
+
     #[test]
     fn test_root_block_columns_has_types() {
         let conn = Connection::establish(cstr!(":memory:")).unwrap();
@@ -988,4 +1920,546 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_explain_input_types() -> anyhow::Result<()> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        conn.exec(
+            cstr!("CREATE TABLE kv(key BIGINT NOT NULL, value TEXT)"),
+            None,
+        )?;
+        conn.exec(
+            cstr!("CREATE TABLE u(id INTEGER PRIMARY KEY, name TEXT NOT NULL, age INT)"),
+            None,
+        )?;
+        conn.exec(cstr!("CREATE INDEX u_name ON u(name)"), None)?;
+
+        let bigint = Some(ColumnType {
+            datatype: DataType::BigInt,
+            nullable: Some(false),
+        });
+        let text = Some(ColumnType {
+            datatype: DataType::Text,
+            nullable: Some(true),
+        });
+        let rowid = Some(ColumnType {
+            datatype: DataType::BigInt,
+            nullable: None,
+        });
+
+        let explanation = explain(&conn, "SELECT * FROM kv WHERE key = ? AND value > ?")?;
+        assert_eq!(explanation.input_types, vec![bigint, text]);
+
+        let explanation = explain(&conn, "INSERT INTO kv VALUES (?, ?)")?;
+        assert_eq!(explanation.input_types, vec![bigint, text]);
+
+        let explanation = explain(&conn, "UPDATE kv SET value = ?2 WHERE key = ?1")?;
+        assert_eq!(explanation.input_types, vec![bigint, text]);
+
+        let explanation = explain(&conn, "SELECT name FROM u WHERE id = ? LIMIT ?")?;
+        assert_eq!(explanation.input_types, vec![rowid, rowid]);
+
+        let explanation = explain(&conn, "SELECT id FROM u WHERE name = ?")?;
+        assert_eq!(
+            explanation.input_types,
+            vec![Some(ColumnType {
+                datatype: DataType::Text,
+                nullable: Some(false),
+            })]
+        );
+
+        let explanation = explain(
+            &conn,
+            "INSERT INTO u(name, age) VALUES (?, CAST(? AS REAL))",
+        )?;
+        assert_eq!(
+            explanation.input_types,
+            vec![
+                Some(ColumnType {
+                    datatype: DataType::Text,
+                    nullable: Some(false),
+                }),
+                Some(ColumnType {
+                    datatype: DataType::Real,
+                    nullable: None,
+                }),
+            ]
+        );
+
+        let explanation = explain(&conn, "SELECT ?")?;
+        assert_eq!(explanation.input_types, vec![None]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_function_types() -> anyhow::Result<()> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        conn.exec(
+            cstr!("CREATE TABLE t(a INTEGER NOT NULL, b TEXT, c BLOB NOT NULL)"),
+            None,
+        )?;
+
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT length(b), upper(b), abs(a), substr(c, 1, 2), typeof(b), round(a), \
+                 datetime('now'), json_extract(b, '$.x'), max(a, 1), last_insert_rowid() FROM t",
+            )?,
+            vec![
+                (DataType::Int, Some(true)),
+                (DataType::Text, Some(true)),
+                (DataType::Int, Some(false)),
+                (DataType::Blob, Some(false)),
+                (DataType::Text, Some(false)),
+                (DataType::Real, Some(false)),
+                (DataType::Text, Some(true)),
+                (DataType::Null, Some(true)),
+                (DataType::Int, Some(false)),
+                (DataType::BigInt, Some(false)),
+            ]
+        );
+        // every argument of a variadic function counts
+        assert_eq!(
+            output_types(&conn, "SELECT min(a, 2, b), max(c, 3, a) FROM t")?,
+            vec![(DataType::Int, Some(true)), (DataType::Blob, Some(false))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_aggregate_types() -> anyhow::Result<()> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        conn.exec(
+            cstr!("CREATE TABLE t(a INTEGER NOT NULL, b TEXT, r REAL NOT NULL)"),
+            None,
+        )?;
+        let sql = "SELECT count(*), avg(a), total(a), sum(a), sum(r), group_concat(b), min(a), \
+                   max(b), json_group_array(a) FROM t";
+
+        // without GROUP BY the aggregates are over no rows for an empty table
+        assert_eq!(
+            output_types(&conn, sql)?,
+            vec![
+                (DataType::BigInt, Some(false)),
+                (DataType::Real, Some(true)),
+                (DataType::Real, Some(false)),
+                (DataType::BigInt, Some(true)),
+                (DataType::Real, Some(true)),
+                (DataType::Text, Some(true)),
+                (DataType::Int, Some(true)),
+                (DataType::Text, Some(true)),
+                (DataType::Text, Some(false)),
+            ]
+        );
+
+        // every group has at least one row
+        assert_eq!(
+            output_types(&conn, &format!("{sql} GROUP BY b"))?,
+            vec![
+                (DataType::BigInt, Some(false)),
+                (DataType::Real, Some(false)),
+                (DataType::Real, Some(false)),
+                (DataType::BigInt, Some(false)),
+                (DataType::Real, Some(false)),
+                (DataType::Text, Some(true)),
+                (DataType::Int, Some(false)),
+                (DataType::Text, Some(true)),
+                (DataType::Text, Some(false)),
+            ]
+        );
+
+        // no row is ever stepped, the result still follows the type of the argument
+        assert_eq!(
+            output_types(&conn, "SELECT sum(r), max(a), sum(a + 1) FROM t WHERE 0")?,
+            vec![
+                (DataType::Real, Some(true)),
+                (DataType::Int, Some(true)),
+                (DataType::Null, Some(true)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_window_types() -> anyhow::Result<()> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        conn.exec(
+            cstr!("CREATE TABLE t(a INTEGER NOT NULL, b TEXT, r REAL NOT NULL)"),
+            None,
+        )?;
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT row_number() OVER w, rank() OVER w, sum(a) OVER w FROM t \
+                 WINDOW w AS (PARTITION BY b ORDER BY a)"
+            )?,
+            vec![
+                (DataType::BigInt, Some(false)),
+                (DataType::BigInt, Some(false)),
+                (DataType::BigInt, Some(false)),
+            ]
+        );
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT a + 1, percent_rank() OVER w, cume_dist() OVER w, ntile(3) OVER w FROM t \
+                 WINDOW w AS (ORDER BY r)"
+            )?,
+            vec![
+                (DataType::Int, Some(false)),
+                (DataType::Real, Some(false)),
+                (DataType::Real, Some(false)),
+                (DataType::BigInt, Some(false)),
+            ]
+        );
+
+        // rows before the first or after the last of the partition are NULL, unless
+        // there is a default
+        assert_eq!(
+            output_types(&conn, "SELECT lag(a) OVER w, lead(r) OVER w, lead(b, 1, 'none') OVER w FROM t WINDOW w AS (ORDER BY a)")?,
+            vec![
+                (DataType::Int, Some(true)),
+                (DataType::Real, Some(true)),
+                (DataType::Text, Some(true)),
+            ]
+        );
+
+        // frames can be empty
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT first_value(a) OVER w, nth_value(r, 2) OVER w, max(a) OVER w FROM t \
+                 WINDOW w AS (ORDER BY a ROWS BETWEEN 2 PRECEDING AND 1 PRECEDING)"
+            )?,
+            vec![
+                (DataType::Int, Some(true)),
+                (DataType::Real, Some(true)),
+                (DataType::Int, Some(true)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_returning_types() -> anyhow::Result<()> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        conn.exec_batch(cstr!(
+            "CREATE TABLE t(a INTEGER NOT NULL, b TEXT, r REAL NOT NULL);
+             CREATE TABLE u(id INTEGER PRIMARY KEY, name TEXT NOT NULL, note TEXT)"
+        ))?;
+        // the returned rows are stored in an ephemeral table and read back at the end
+        assert_eq!(
+            output_types(
+                &conn,
+                "INSERT INTO t VALUES (1, NULL, 2.5) RETURNING a, b, r, a * 2, rowid"
+            )?,
+            vec![
+                (DataType::Int, Some(false)),
+                (DataType::Text, Some(true)),
+                (DataType::Real, Some(false)),
+                (DataType::Int, Some(false)),
+                (DataType::BigInt, Some(false)),
+            ]
+        );
+        // a NULL written to a column has the declared type of the column
+        assert_eq!(
+            output_types(&conn, "UPDATE t SET b = NULL RETURNING b")?,
+            vec![(DataType::Text, Some(true))]
+        );
+        // a NOT NULL column is not null once it has been inserted
+        assert_eq!(
+            output_types(
+                &conn,
+                "INSERT INTO u(name, note) SELECT b, b FROM t RETURNING id, name, note"
+            )?,
+            vec![
+                (DataType::BigInt, Some(false)),
+                (DataType::Text, Some(false)),
+                (DataType::Text, Some(true)),
+            ]
+        );
+        assert_eq!(
+            output_types(
+                &conn,
+                "UPDATE u SET note = upper(name) WHERE id > ? RETURNING id, name, note"
+            )?,
+            vec![
+                (DataType::BigInt, Some(false)),
+                (DataType::Text, Some(false)),
+                (DataType::Text, Some(false)),
+            ]
+        );
+        assert_eq!(
+            output_types(&conn, "DELETE FROM t WHERE a = ? RETURNING a - 1, b, r")?,
+            vec![
+                (DataType::Int, Some(false)),
+                (DataType::Text, Some(true)),
+                (DataType::Real, Some(false)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_outer_join_types() -> anyhow::Result<()> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        conn.exec_batch(cstr!(
+            "CREATE TABLE p(id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+             CREATE TABLE c(id INTEGER PRIMARY KEY, pid INTEGER NOT NULL, v TEXT NOT NULL);
+             CREATE TABLE g(id INTEGER PRIMARY KEY, cid INTEGER NOT NULL, w REAL NOT NULL);
+             CREATE VIEW cv AS SELECT c.v, g.w FROM c LEFT JOIN g ON g.cid = c.id"
+        ))?;
+        // the right side of a LEFT JOIN is a row of NULLs when nothing matches
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT p.name, c.v, c.id, c.rowid FROM p LEFT JOIN c ON c.pid = p.id"
+            )?,
+            vec![
+                (DataType::Text, Some(false)),
+                (DataType::Text, Some(true)),
+                (DataType::BigInt, Some(true)),
+                (DataType::BigInt, Some(true)),
+            ]
+        );
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT p.name, c.v FROM p RIGHT JOIN c ON c.pid = p.id"
+            )?,
+            vec![(DataType::Text, Some(true)), (DataType::Text, Some(false))]
+        );
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT p.name, c.v FROM p FULL JOIN c ON c.pid = p.id"
+            )?,
+            vec![(DataType::Text, Some(true)), (DataType::Text, Some(true))]
+        );
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT p.name, c.v, g.w FROM p LEFT JOIN c ON c.pid = p.id
+                 LEFT JOIN g ON g.cid = c.id"
+            )?,
+            vec![
+                (DataType::Text, Some(false)),
+                (DataType::Text, Some(true)),
+                (DataType::Real, Some(true)),
+            ]
+        );
+        // through a sorter and through a view
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT p.name, c.v FROM p LEFT JOIN c ON c.pid = p.id ORDER BY c.v"
+            )?,
+            vec![(DataType::Text, Some(false)), (DataType::Text, Some(true))]
+        );
+        assert_eq!(
+            output_types(&conn, "SELECT v, w FROM cv")?,
+            vec![(DataType::Text, Some(false)), (DataType::Real, Some(true))]
+        );
+        // an inner join keeps NOT NULL columns not null
+        assert_eq!(
+            output_types(&conn, "SELECT p.name, c.v FROM p JOIN c ON c.pid = p.id")?,
+            vec![(DataType::Text, Some(false)), (DataType::Text, Some(false))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_not_null_narrowing() -> anyhow::Result<()> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        conn.exec_batch(cstr!("CREATE TABLE t(a INTEGER NOT NULL, b TEXT, c REAL)"))?;
+        let text = |nullable| vec![(DataType::Text, Some(nullable))];
+
+        assert_eq!(
+            output_types(&conn, "SELECT b FROM t WHERE b IS NOT NULL")?,
+            text(false)
+        );
+        assert_eq!(
+            output_types(&conn, "SELECT b || 'x' FROM t WHERE b NOT NULL")?,
+            text(false)
+        );
+        // comparisons are false with NULL, whether they jump or fall through on a match
+        assert_eq!(
+            output_types(&conn, "SELECT b FROM t WHERE b = ?")?,
+            text(false)
+        );
+        assert_eq!(
+            output_types(&conn, "SELECT b FROM t WHERE b > 'x'")?,
+            text(false)
+        );
+        assert_eq!(
+            output_types(&conn, "SELECT b FROM t WHERE b IN ('x', 'y')")?,
+            text(false)
+        );
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT b, c FROM t WHERE b IS NOT NULL AND c BETWEEN 1 AND 2 ORDER BY b"
+            )?,
+            vec![(DataType::Text, Some(false)), (DataType::Real, Some(false))]
+        );
+        // no narrowing where NULL can pass the filter
+        assert_eq!(
+            output_types(&conn, "SELECT b FROM t WHERE b IS NULL")?,
+            text(true)
+        );
+        assert_eq!(
+            output_types(&conn, "SELECT b FROM t WHERE b IS 'x'")?,
+            text(true)
+        );
+        assert_eq!(
+            output_types(&conn, "SELECT b FROM t WHERE b IS NOT NULL OR a > 1")?,
+            text(true)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_subroutine_types() -> anyhow::Result<()> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        conn.exec_batch(cstr!(
+            "CREATE TABLE p(id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+             CREATE TABLE c(id INTEGER PRIMARY KEY, pid INTEGER NOT NULL, v TEXT NOT NULL);
+             CREATE TABLE g(id INTEGER PRIMARY KEY, cid INTEGER NOT NULL, w REAL NOT NULL)"
+        ))?;
+        // the subquery is materialized by a subroutine before the join
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT p.name, x.v, x.w FROM p LEFT JOIN \
+                 (SELECT c.v, g.w FROM c JOIN g ON g.cid = c.id) AS x ON x.v = p.name"
+            )?,
+            vec![
+                (DataType::Text, Some(false)),
+                (DataType::Text, Some(true)),
+                (DataType::Real, Some(true)),
+            ]
+        );
+        // groups are output by a subroutine, called once more after the last row
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT pid, max(v) FROM c GROUP BY pid ORDER BY 2 LIMIT 2"
+            )?,
+            vec![(DataType::Int, Some(false)), (DataType::Text, Some(false))]
+        );
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT name, (SELECT max(v) FROM c WHERE c.pid = p.id) FROM p"
+            )?,
+            vec![(DataType::Text, Some(false)), (DataType::Text, Some(true))]
+        );
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT name FROM p WHERE name IN (SELECT v FROM c WHERE c.pid = p.id)"
+            )?,
+            vec![(DataType::Text, Some(false))]
+        );
+        // windows over different orders are computed by chained subroutines
+        assert_eq!(
+            output_types(
+                &conn,
+                "SELECT v, sum(id) OVER (PARTITION BY pid), rank() OVER (ORDER BY v) FROM c"
+            )?,
+            vec![
+                (DataType::Text, Some(false)),
+                (DataType::BigInt, Some(false)),
+                (DataType::BigInt, Some(false)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_many_branches() -> anyhow::Result<()> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        conn.exec_batch(cstr!(
+            "CREATE TABLE t(a INTEGER NOT NULL, b TEXT, r REAL NOT NULL);
+             CREATE TABLE u(id INTEGER PRIMARY KEY, name TEXT NOT NULL)"
+        ))?;
+        // every outer join doubles the paths through the loop
+        let joins: Vec<_> = (0..20)
+            .map(|i| format!("LEFT JOIN u AS u{i} ON u{i}.id = t.a + {i}"))
+            .collect();
+        assert_eq!(
+            output_types(
+                &conn,
+                &format!("SELECT t.a, u19.name FROM t {}", joins.join(" "))
+            )?,
+            vec![(DataType::Int, Some(false)), (DataType::Text, Some(true))]
+        );
+        let ors: Vec<_> = (0..40)
+            .map(|i| format!("(a = {i} AND b = 'x{i}' OR r > {i})"))
+            .collect();
+        assert_eq!(
+            output_types(
+                &conn,
+                &format!("SELECT a, b FROM t WHERE {}", ors.join(" OR "))
+            )?,
+            vec![(DataType::Int, Some(false)), (DataType::Text, Some(true))]
+        );
+        let cases: Vec<_> = (0..8)
+            .map(|_| {
+                let whens: Vec<_> = (0..8).map(|i| format!("WHEN a = {i} THEN {i}")).collect();
+                format!("CASE {} ELSE a END", whens.join(" "))
+            })
+            .collect();
+        assert_eq!(
+            output_types(&conn, &format!("SELECT {} FROM t", cases.join(", ")))?,
+            vec![(DataType::Int, Some(false)); 8]
+        );
+        let filters: Vec<_> = (0..10)
+            .map(|i| format!("max(r) FILTER (WHERE a > {i})"))
+            .collect();
+        assert_eq!(
+            output_types(
+                &conn,
+                &format!("SELECT {} FROM t GROUP BY b", filters.join(", "))
+            )?,
+            vec![(DataType::Real, Some(true)); 10]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_limits() -> anyhow::Result<()> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        conn.exec_batch(cstr!("CREATE TABLE t(a INTEGER NOT NULL, b TEXT)"))?;
+        let explain = |limits: &ExplainLimits| {
+            explain_with_columns(
+                &conn,
+                &root_block_columns(&conn)?,
+                &FunctionSignatures::default(),
+                limits,
+                "SELECT a FROM t WHERE a = 1 OR b = 'x'",
+            )
+        };
+
+        assert!(explain(&ExplainLimits::default()).is_ok());
+        assert!(matches!(
+            explain(&ExplainLimits {
+                max_states: 1,
+                ..ExplainLimits::default()
+            }),
+            Err(ExplainerError::AnalysisIncomplete(_))
+        ));
+        assert!(matches!(
+            explain(&ExplainLimits {
+                max_instructions: 10,
+                ..ExplainLimits::default()
+            }),
+            Err(ExplainerError::AnalysisIncomplete(AnalysisIncomplete {
+                instructions: 11,
+                ..
+            }))
+        ));
+        Ok(())
+    }
 }
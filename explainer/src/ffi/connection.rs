@@ -1,26 +1,58 @@
-use libsqlite3_sys::{sqlite3, sqlite3_close, sqlite3_open, sqlite3_prepare_v2, SQLITE_OK};
-use std::{ffi::CStr, ptr::NonNull};
+use libsqlite3_sys::{
+    sqlite3, sqlite3_busy_timeout, sqlite3_changes64, sqlite3_close, sqlite3_context,
+    sqlite3_create_function_v2, sqlite3_create_window_function, sqlite3_db_readonly, sqlite3_exec,
+    sqlite3_open_v2, sqlite3_prepare_v2, sqlite3_result_error, sqlite3_set_authorizer,
+    sqlite3_total_changes64, sqlite3_value, SQLITE_DENY, SQLITE_DETERMINISTIC, SQLITE_OK,
+    SQLITE_OPEN_CREATE, SQLITE_OPEN_READONLY, SQLITE_OPEN_READWRITE, SQLITE_OPEN_URI, SQLITE_UTF8,
+};
+use std::{
+    cell::Cell,
+    ffi::{c_void, CStr, CString},
+    os::raw::{c_char, c_int},
+    ptr::NonNull,
+    time::Duration,
+};
 
-use crate::ffi::error::SqliteError;
+use crate::{cstr, ffi::error::SqliteError};
 
 use super::{row::Row, statement::Statement};
 
+/// Decides, as a statement is prepared, whether it may do `action`, one of the
+/// `SQLITE_READ`, `SQLITE_PRAGMA`, ... action codes, with its first two arguments.
+pub type Authorizer = fn(action: i32, arg1: Option<&str>, arg2: Option<&str>) -> bool;
+
 #[derive(Debug)]
-pub struct Connection(NonNull<sqlite3>);
+pub struct Connection {
+    handle: NonNull<sqlite3>,
+    authorizer: Cell<Option<Authorizer>>,
+}
+
+// the authorizer is only set once SQLite took it, a panic can't leave it half updated
+impl std::panic::RefUnwindSafe for Connection {}
 
 impl Connection {
+    /// Open the database at `path` read-write, creating it if it doesn't exist.
     pub fn establish(path: &CStr) -> Result<Connection, SqliteError> {
-        let mut handle = std::ptr::null_mut();
-        let _ = unsafe { sqlite3_open(path.as_ptr(), &mut handle) };
-        if handle.is_null() {
-            return Err(SqliteError::new(handle));
-        }
-        let connection = unsafe { Connection(NonNull::new_unchecked(handle)) };
-        Ok(connection)
+        OpenOptions::new().open(path)
     }
 
     pub fn as_ptr(&self) -> *mut sqlite3 {
-        self.0.as_ptr()
+        self.handle.as_ptr()
+    }
+
+    /// Number of rows changed by the most recent INSERT, UPDATE or DELETE.
+    pub fn changes(&self) -> usize {
+        unsafe { sqlite3_changes64(self.as_ptr()) as usize }
+    }
+
+    /// Number of rows changed since the connection was opened, including by triggers.
+    pub fn total_changes(&self) -> usize {
+        unsafe { sqlite3_total_changes64(self.as_ptr()) as usize }
+    }
+
+    /// Whether the main database can't be written, such as when it was opened read-only.
+    pub fn is_read_only(&self) -> bool {
+        unsafe { sqlite3_db_readonly(self.as_ptr(), cstr!("main").as_ptr()) == 1 }
     }
 
     pub fn prepare(&self, sql: &CStr) -> Result<Statement, SqliteError> {
@@ -41,6 +73,27 @@ impl Connection {
         Ok(statement)
     }
 
+    /// Prepare the first statement in `sql`.
+    ///
+    /// Returns the statement, or `None` if `sql` holds only whitespace and comments,
+    /// together with the number of bytes of `sql` it consumed.
+    pub fn prepare_with_tail(&self, sql: &CStr) -> Result<(Option<Statement>, usize), SqliteError> {
+        let mut handle = std::ptr::null_mut();
+        let mut tail = std::ptr::null();
+        let status =
+            unsafe { sqlite3_prepare_v2(self.as_ptr(), sql.as_ptr(), -1, &mut handle, &mut tail) };
+        if status != SQLITE_OK {
+            return Err(SqliteError::new(self.as_ptr()));
+        }
+        let consumed = if tail.is_null() {
+            sql.to_bytes().len()
+        } else {
+            tail as usize - sql.as_ptr() as usize
+        };
+        let statement = NonNull::new(handle).map(Statement::new);
+        Ok((statement, consumed))
+    }
+
     pub fn exec(
         &self,
         query: &CStr,
@@ -56,6 +109,105 @@ impl Connection {
         Ok(())
     }
 
+    /// Run every statement in `sql`, discarding the rows they return.
+    pub fn exec_batch(&self, sql: &CStr) -> Result<(), SqliteError> {
+        let status = unsafe {
+            sqlite3_exec(
+                self.as_ptr(),
+                sql.as_ptr(),
+                None,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if status != SQLITE_OK {
+            return Err(SqliteError::new(self.as_ptr()));
+        }
+        Ok(())
+    }
+
+    /// Declare a function that fails when called, so statements using it can be
+    /// prepared and analysed without its implementation.
+    pub fn declare_function(
+        &self,
+        name: &CStr,
+        argc: i32,
+        aggregate: bool,
+    ) -> Result<(), SqliteError> {
+        let flags = SQLITE_UTF8 | SQLITE_DETERMINISTIC;
+        let status = unsafe {
+            if aggregate {
+                sqlite3_create_window_function(
+                    self.as_ptr(),
+                    name.as_ptr(),
+                    argc,
+                    flags,
+                    std::ptr::null_mut(),
+                    Some(declared_function),
+                    Some(declared_final),
+                    Some(declared_final),
+                    Some(declared_function),
+                    None,
+                )
+            } else {
+                sqlite3_create_function_v2(
+                    self.as_ptr(),
+                    name.as_ptr(),
+                    argc,
+                    flags,
+                    std::ptr::null_mut(),
+                    Some(declared_function),
+                    None,
+                    None,
+                    None,
+                )
+            }
+        };
+        if status != SQLITE_OK {
+            return Err(SqliteError::new(self.as_ptr()));
+        }
+        Ok(())
+    }
+
+    /// Check every statement prepared from now on with `authorizer`, a statement doing
+    /// an action it denies fails to prepare. `None` removes the authorizer.
+    pub fn set_authorizer(&self, authorizer: Option<Authorizer>) -> Result<(), SqliteError> {
+        let status = unsafe {
+            match authorizer {
+                Some(authorizer) => sqlite3_set_authorizer(
+                    self.as_ptr(),
+                    Some(authorize),
+                    authorizer as *mut c_void,
+                ),
+                None => sqlite3_set_authorizer(self.as_ptr(), None, std::ptr::null_mut()),
+            }
+        };
+        if status != SQLITE_OK {
+            return Err(SqliteError::new(self.as_ptr()));
+        }
+        self.authorizer.set(authorizer);
+        Ok(())
+    }
+
+    /// The authorizer last set by [`Connection::set_authorizer`]. One set through
+    /// [`Connection::as_ptr`] isn't known.
+    pub fn authorizer(&self) -> Option<Authorizer> {
+        self.authorizer.get()
+    }
+
+    /// Open a `SAVEPOINT` named `name`, what is run on the connection until the
+    /// [`Savepoint`] is dropped is rolled back then.
+    pub fn savepoint(&self, name: &str) -> Result<Savepoint<'_>, SqliteError> {
+        let name = name.replace('"', "\"\"");
+        let sql = |sql: String| CString::new(sql).expect("savepoint name holds a NUL byte");
+        self.exec_batch(&sql(format!("SAVEPOINT \"{name}\"")))?;
+        Ok(Savepoint {
+            conn: self,
+            rollback: sql(format!("ROLLBACK TO \"{name}\"; RELEASE \"{name}\"")),
+            done: false,
+        })
+    }
+
     pub fn load_all<F, T, E>(&self, query: &CStr, f: F) -> Result<Vec<T>, E>
     where
         F: Fn(&Row) -> Result<T, E>,
@@ -83,15 +235,232 @@ impl Connection {
     }
 }
 
+/// A savepoint of a [`Connection`], rolled back and released when dropped.
+#[derive(Debug)]
+pub struct Savepoint<'a> {
+    conn: &'a Connection,
+    rollback: CString,
+    done: bool,
+}
+
+impl Savepoint<'_> {
+    /// Roll back and release the savepoint, reporting a failure that dropping it ignores.
+    pub fn rollback(mut self) -> Result<(), SqliteError> {
+        self.done = true;
+        self.conn.exec_batch(&self.rollback)
+    }
+}
+
+impl Drop for Savepoint<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.conn.exec_batch(&self.rollback);
+        }
+    }
+}
+
+/// How to open a database, for [`OpenOptions::open`].
+///
+/// ```no_run
+/// # use explainer::{cstr, ffi::connection::OpenOptions};
+/// // analyse a snapshot without writing to it, or creating it if the path is wrong
+/// let conn = OpenOptions::new()
+///     .read_only(true)
+///     .immutable(true)
+///     .open(cstr!("snapshot.db"))?;
+/// # Ok::<(), explainer::ffi::error::SqliteError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    read_only: bool,
+    create: bool,
+    uri: bool,
+    immutable: bool,
+    nolock: bool,
+    vfs: Option<CString>,
+    busy_timeout: Option<Duration>,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenOptions {
+    /// Read-write, creating the database if it doesn't exist.
+    pub fn new() -> OpenOptions {
+        OpenOptions {
+            read_only: false,
+            create: true,
+            uri: false,
+            immutable: false,
+            nolock: false,
+            vfs: None,
+            busy_timeout: None,
+        }
+    }
+
+    /// Open the database read-only, which never creates it.
+    pub fn read_only(&mut self, read_only: bool) -> &mut Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Create the database if it doesn't exist, unless it is opened read-only.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Take the path as a `file:` URI, with query parameters like `mode=ro`.
+    pub fn uri(&mut self, uri: bool) -> &mut Self {
+        self.uri = uri;
+        self
+    }
+
+    /// Assume the database can't change while it is open, so it is read without locks
+    /// or a journal.
+    pub fn immutable(&mut self, immutable: bool) -> &mut Self {
+        self.immutable = immutable;
+        self
+    }
+
+    /// Read the database without taking file locks.
+    pub fn nolock(&mut self, nolock: bool) -> &mut Self {
+        self.nolock = nolock;
+        self
+    }
+
+    /// Name of the VFS to open the database with, rather than the default one.
+    pub fn vfs(&mut self, vfs: &CStr) -> &mut Self {
+        self.vfs = Some(vfs.to_owned());
+        self
+    }
+
+    /// Retry for up to `timeout` while another connection holds a lock.
+    pub fn busy_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.busy_timeout = Some(timeout);
+        self
+    }
+
+    pub fn open(&self, path: &CStr) -> Result<Connection, SqliteError> {
+        let mut flags = if self.read_only {
+            SQLITE_OPEN_READONLY
+        } else if self.create {
+            SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE
+        } else {
+            SQLITE_OPEN_READWRITE
+        };
+        let params: Vec<&str> = [(self.immutable, "immutable=1"), (self.nolock, "nolock=1")]
+            .into_iter()
+            .filter_map(|(set, param)| set.then_some(param))
+            .collect();
+        let uri;
+        let path = if params.is_empty() {
+            path
+        } else {
+            uri = self.with_params(path, &params);
+            &uri
+        };
+        if self.uri || !params.is_empty() {
+            flags |= SQLITE_OPEN_URI;
+        }
+
+        let mut handle = std::ptr::null_mut();
+        let vfs = self.vfs.as_deref().map_or(std::ptr::null(), CStr::as_ptr);
+        let status = unsafe { sqlite3_open_v2(path.as_ptr(), &mut handle, flags, vfs) };
+        // the handle is null only when SQLite can't allocate it
+        let Some(handle) = NonNull::new(handle) else {
+            return Err(SqliteError::new(std::ptr::null_mut()));
+        };
+        // closes the handle of a database that failed to open
+        let connection = Connection {
+            handle,
+            authorizer: Cell::new(None),
+        };
+        if status != SQLITE_OK {
+            return Err(SqliteError::new(connection.as_ptr()));
+        }
+        if let Some(timeout) = self.busy_timeout {
+            let ms = c_int::try_from(timeout.as_millis()).unwrap_or(c_int::MAX);
+            unsafe { sqlite3_busy_timeout(connection.as_ptr(), ms) };
+        }
+        Ok(connection)
+    }
+
+    /// `path` as a `file:` URI with the query parameters `params` added.
+    fn with_params(&self, path: &CStr, params: &[&str]) -> CString {
+        let path = path.to_bytes();
+        let mut uri = Vec::new();
+        if self.uri && path.starts_with(b"file:") {
+            uri.extend_from_slice(path);
+        } else {
+            uri.extend_from_slice(b"file:");
+            for &b in path {
+                match b {
+                    b'%' | b'?' | b'#' => uri.extend_from_slice(format!("%{b:02X}").as_bytes()),
+                    b => uri.push(b),
+                }
+            }
+        }
+        let mut separator = if uri.contains(&b'?') { b'&' } else { b'?' };
+        for param in params {
+            uri.push(separator);
+            uri.extend_from_slice(param.as_bytes());
+            separator = b'&';
+        }
+        // built from the bytes of a `CStr` and ASCII without NUL
+        CString::new(uri).unwrap_or_default()
+    }
+}
+
+unsafe extern "C" fn declared_function(
+    ctx: *mut sqlite3_context,
+    _argc: c_int,
+    _argv: *mut *mut sqlite3_value,
+) {
+    declared_final(ctx)
+}
+
+unsafe extern "C" fn declared_final(ctx: *mut sqlite3_context) {
+    let message = cstr!("function is declared for analysis only");
+    sqlite3_result_error(ctx, message.as_ptr(), -1);
+}
+
+unsafe extern "C" fn authorize(
+    authorizer: *mut c_void,
+    action: c_int,
+    arg1: *const c_char,
+    arg2: *const c_char,
+    _database: *const c_char,
+    _trigger: *const c_char,
+) -> c_int {
+    let authorizer = std::mem::transmute::<*mut c_void, Authorizer>(authorizer);
+    // arguments that aren't UTF-8, and panics, deny rather than unwind into SQLite
+    let arg = |arg: *const c_char| match arg.is_null() {
+        true => Some(None),
+        false => CStr::from_ptr(arg).to_str().ok().map(Some),
+    };
+    let allowed = match (arg(arg1), arg(arg2)) {
+        (Some(arg1), Some(arg2)) => {
+            std::panic::catch_unwind(|| authorizer(action, arg1, arg2)).unwrap_or(false)
+        }
+        _ => false,
+    };
+    if allowed {
+        SQLITE_OK
+    } else {
+        SQLITE_DENY
+    }
+}
+
 impl Drop for Connection {
     fn drop(&mut self) {
         unsafe {
-            let ret = sqlite3_close(self.0.as_ptr());
+            let ret = sqlite3_close(self.as_ptr());
             if ret != SQLITE_OK {
-                let msg = format!(
-                    "sqlite3_close failed: {}",
-                    SqliteError::new(self.0.as_ptr())
-                );
+                let msg = format!("sqlite3_close failed: {}", SqliteError::new(self.as_ptr()));
                 eprintln!("{msg}");
                 panic!("{}", msg);
             }
@@ -102,7 +471,7 @@ impl Drop for Connection {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::ffi::CString;
+    use crate::ffi::error::ErrorCode;
 
     #[test]
     fn test_open() {
@@ -111,6 +480,60 @@ mod tests {
         assert!(!conn.as_ptr().is_null());
     }
 
+    #[test]
+    fn test_open_options() {
+        let dir = std::env::temp_dir().join(format!("explainer-open-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a?b.db");
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+
+        // read-only never creates the database
+        let error = OpenOptions::new()
+            .read_only(true)
+            .open(&c_path)
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::CannotOpen);
+        assert!(OpenOptions::new().create(false).open(&c_path).is_err());
+        assert!(!path.exists());
+
+        let conn = Connection::establish(&c_path).unwrap();
+        conn.exec_batch(cstr!("CREATE TABLE t(a)")).unwrap();
+        drop(conn);
+
+        let conn = OpenOptions::new()
+            .read_only(true)
+            .busy_timeout(Duration::from_secs(1))
+            .open(&c_path)
+            .unwrap();
+        assert!(conn.prepare(cstr!("SELECT a FROM t")).is_ok());
+        let error = conn
+            .exec_batch(cstr!("INSERT INTO t VALUES (1)"))
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::ReadOnly);
+
+        for options in [
+            OpenOptions::new().read_only(true).immutable(true).clone(),
+            OpenOptions::new().read_only(true).nolock(true).clone(),
+            OpenOptions::new().immutable(true).nolock(true).clone(),
+        ] {
+            let conn = options.open(&c_path).unwrap();
+            assert!(conn.prepare(cstr!("SELECT a FROM t")).is_ok());
+        }
+        let uri = CString::new(format!("file:{}?mode=ro", dir.join("a%3Fb.db").display())).unwrap();
+        let conn = OpenOptions::new()
+            .uri(true)
+            .immutable(true)
+            .open(&uri)
+            .unwrap();
+        assert!(conn.prepare(cstr!("SELECT a FROM t")).is_ok());
+        assert!(OpenOptions::new()
+            .vfs(cstr!("no-such-vfs"))
+            .open(&c_path)
+            .is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_prepare() {
         let path = CString::new(":memory:").unwrap();
@@ -120,6 +543,24 @@ mod tests {
         assert!(!stmt.as_ptr().is_null());
     }
 
+    #[test]
+    fn test_prepare_with_tail() {
+        let path = CString::new(":memory:").unwrap();
+        let conn = Connection::establish(&path).unwrap();
+        let query = CString::new("SELECT 1; SELECT 2; -- done").unwrap();
+        let (stmt, consumed) = conn.prepare_with_tail(&query).unwrap();
+        assert!(stmt.is_some());
+        assert_eq!(consumed, "SELECT 1;".len());
+        let tail = CStr::from_bytes_with_nul(&query.as_bytes_with_nul()[consumed..]).unwrap();
+        let (stmt, consumed) = conn.prepare_with_tail(tail).unwrap();
+        assert!(stmt.is_some());
+        assert_eq!(consumed, " SELECT 2;".len());
+        let (stmt, _) = conn
+            .prepare_with_tail(&CString::new(" -- done").unwrap())
+            .unwrap();
+        assert!(stmt.is_none());
+    }
+
     #[test]
     fn test_exec() {
         let path = CString::new(":memory:").unwrap();
@@ -139,6 +580,72 @@ mod tests {
         assert_eq!(count, 1);
     }
 
+    #[test]
+    fn test_exec_batch() {
+        let path = CString::new(":memory:").unwrap();
+        let conn = Connection::establish(&path).unwrap();
+        let query = CString::new("CREATE TABLE a(x); CREATE TABLE b(y); INSERT INTO a VALUES (1);")
+            .unwrap();
+        conn.exec_batch(&query).unwrap();
+        let query = CString::new("SELECT count(*) FROM a, b").unwrap();
+        assert!(conn.prepare(&query).is_ok());
+        let query = CString::new("CREATE TABLE c(z); CREATE TABLE a(x);").unwrap();
+        assert!(conn.exec_batch(&query).is_err());
+    }
+
+    #[test]
+    fn test_savepoint() {
+        let conn = Connection::establish(cstr!(":memory:")).unwrap();
+        let count = |conn: &Connection| {
+            conn.load_all(cstr!("SELECT count(*) FROM sqlite_schema"), |row| {
+                anyhow::Ok(row.column_int(0))
+            })
+            .unwrap()
+        };
+        let savepoint = conn.savepoint("a\"b").unwrap();
+        conn.exec_batch(cstr!("CREATE TABLE t(a)")).unwrap();
+        assert_eq!(count(&conn), vec![1]);
+        savepoint.rollback().unwrap();
+        assert_eq!(count(&conn), vec![0]);
+
+        // dropped, on an early return or a panic
+        let result = std::panic::catch_unwind(|| {
+            let _savepoint = conn.savepoint("s").unwrap();
+            conn.exec_batch(cstr!("CREATE TABLE t(a)")).unwrap();
+            panic!("analysis failed");
+        });
+        assert!(result.is_err());
+        assert_eq!(count(&conn), vec![0]);
+        // no transaction is left open
+        conn.exec_batch(cstr!("BEGIN; COMMIT")).unwrap();
+
+        assert!(!conn.is_read_only());
+        let conn = OpenOptions::new()
+            .read_only(true)
+            .open(cstr!(":memory:"))
+            .unwrap();
+        assert!(conn.is_read_only());
+    }
+
+    #[test]
+    fn test_set_authorizer() {
+        let conn = Connection::establish(cstr!(":memory:")).unwrap();
+        conn.exec_batch(cstr!("CREATE TABLE t(a)")).unwrap();
+        conn.set_authorizer(Some(|action, arg1, _| {
+            action != libsqlite3_sys::SQLITE_DELETE || arg1 != Some("t")
+        }))
+        .unwrap();
+        assert!(conn.authorizer().is_some());
+        assert!(conn.prepare(cstr!("INSERT INTO t VALUES (1)")).is_ok());
+        let Err(error) = conn.prepare(cstr!("DELETE FROM t")) else {
+            panic!("DELETE FROM t was authorized");
+        };
+        assert_eq!(error.code(), ErrorCode::AuthorizationForStatementDenied);
+        conn.set_authorizer(None).unwrap();
+        assert!(conn.authorizer().is_none());
+        assert!(conn.prepare(cstr!("DELETE FROM t")).is_ok());
+    }
+
     #[test]
     fn test_load_all() {
         let path = CString::new(":memory:").unwrap();
@@ -1,4 +1,6 @@
-use libsqlite3_sys::{sqlite3, sqlite3_errmsg, sqlite3_extended_errcode};
+use libsqlite3_sys::{
+    code_to_str, sqlite3, sqlite3_errmsg, sqlite3_error_offset, sqlite3_extended_errcode,
+};
 use std::error::Error;
 use std::{
     ffi::CStr,
@@ -7,10 +9,16 @@ use std::{
     str::from_utf8_unchecked,
 };
 
+/// Extended result code of a failure, its `extended_code` one of the
+/// `SQLITE_*` result codes with the primary `code` in its low 8 bits.
+pub use libsqlite3_sys::Error as ExtendedCode;
+pub use libsqlite3_sys::ErrorCode;
+
 #[derive(Debug)]
 pub struct SqliteError {
-    code: i32,
+    code: ExtendedCode,
     message: String,
+    offset: Option<usize>,
 }
 
 impl SqliteError {
@@ -23,18 +31,95 @@ impl SqliteError {
 
             from_utf8_unchecked(CStr::from_ptr(msg).to_bytes())
         };
+        // -1 when the error isn't about a token of the SQL
+        let offset = unsafe { sqlite3_error_offset(handle) };
 
         Self {
-            code,
+            code: ExtendedCode::new(code),
             message: message.to_owned(),
+            offset: usize::try_from(offset).ok(),
         }
     }
+
+    /// Primary result code of the failure.
+    pub fn code(&self) -> ErrorCode {
+        self.code.code
+    }
+
+    /// Extended result code of the failure.
+    pub fn extended_code(&self) -> ExtendedCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Byte offset in the SQL of the token the failure is about, if there is one.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
 }
 
 impl Display for SqliteError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "(code: {}) {}", self.code, self.message)
+        fmt_error(f, self.code, &self.message)
     }
 }
 
 impl Error for SqliteError {}
+
+/// Readable name of the extended result code `code`, the name of its primary code for
+/// extended codes without one.
+pub fn code_name(code: ExtendedCode) -> &'static str {
+    match code_to_str(code.extended_code) {
+        "Unknown error code" => code_to_str(code.extended_code & 0xff),
+        name => name,
+    }
+}
+
+pub(crate) fn fmt_error(f: &mut Formatter<'_>, code: ExtendedCode, message: &str) -> fmt::Result {
+    let name = code_name(code);
+    write!(f, "{message} ({name}, code {})", code.extended_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cstr, ffi::connection::Connection};
+    use libsqlite3_sys::{SQLITE_CONSTRAINT_PRIMARYKEY, SQLITE_ERROR, SQLITE_IOERR_CORRUPTFS};
+
+    #[test]
+    fn test_error_codes() {
+        let conn = Connection::establish(cstr!(":memory:")).unwrap();
+        conn.exec_batch(cstr!("CREATE TABLE t(id INTEGER PRIMARY KEY)"))
+            .unwrap();
+
+        let error = conn.exec_batch(cstr!("SELECT * FROM missing")).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::Unknown);
+        assert_eq!(error.extended_code(), ExtendedCode::new(SQLITE_ERROR));
+        assert_eq!(
+            error.to_string(),
+            "no such table: missing (SQL error or missing database, code 1)"
+        );
+
+        let error = conn
+            .exec_batch(cstr!("INSERT INTO t VALUES (1); INSERT INTO t VALUES (1)"))
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::ConstraintViolation);
+        assert_eq!(
+            error.extended_code().extended_code,
+            SQLITE_CONSTRAINT_PRIMARYKEY
+        );
+        assert_eq!(
+            code_name(error.extended_code()),
+            "A PRIMARY KEY constraint failed"
+        );
+
+        // SQLITE_IOERR_CORRUPTFS has no name of its own
+        assert_eq!(
+            code_name(ExtendedCode::new(SQLITE_IOERR_CORRUPTFS)),
+            "Some kind of disk I/O error occurred"
+        );
+    }
+}
@@ -3,6 +3,8 @@ use libsqlite3_sys::{
     sqlite3_column_int64,
 };
 
+use crate::types::DataType;
+
 use super::statement::Statement;
 
 pub struct Row<'a>(&'a Statement);
@@ -16,6 +18,10 @@ impl<'a> Row<'a> {
         self.0.column_count()
     }
 
+    pub fn column_is_null(&self, index: usize) -> bool {
+        self.0.column_type(index).datatype == DataType::Null
+    }
+
     pub fn column_int(&self, index: usize) -> i32 {
         unsafe { sqlite3_column_int(self.0.as_ptr(), index as i32) }
     }
@@ -1,16 +1,20 @@
 use std::{
-    ffi::{c_int, CStr},
+    ffi::{c_char, c_int, CStr},
     ptr::NonNull,
 };
 
 use libsqlite3_sys::{
-    sqlite3, sqlite3_bind_parameter_count, sqlite3_column_count, sqlite3_column_database_name,
-    sqlite3_column_origin_name, sqlite3_column_table_name, sqlite3_column_type, sqlite3_db_handle,
-    sqlite3_finalize, sqlite3_step, sqlite3_stmt, sqlite3_stmt_readonly,
-    sqlite3_table_column_metadata, SQLITE_DONE, SQLITE_OK, SQLITE_ROW,
+    sqlite3, sqlite3_bind_blob, sqlite3_bind_double, sqlite3_bind_int, sqlite3_bind_int64,
+    sqlite3_bind_null, sqlite3_bind_parameter_count, sqlite3_bind_parameter_name,
+    sqlite3_bind_text, sqlite3_column_count, sqlite3_column_database_name, sqlite3_column_decltype,
+    sqlite3_column_name, sqlite3_column_origin_name, sqlite3_column_table_name,
+    sqlite3_column_type, sqlite3_db_handle, sqlite3_finalize, sqlite3_step, sqlite3_stmt,
+    sqlite3_stmt_readonly, sqlite3_table_column_metadata, SQLITE_DONE, SQLITE_OK, SQLITE_ROW,
+    SQLITE_TRANSIENT,
 };
 
-use crate::types::{ColumnType, DataType};
+use crate::error::ExplainerError;
+use crate::types::{ColumnOrigin, ColumnType, DataType};
 
 use super::error::SqliteError;
 
@@ -39,16 +43,106 @@ impl Statement {
         unsafe { sqlite3_bind_parameter_count(self.0.as_ptr()) as usize }
     }
 
+    /// Name of the parameter with the 1-based `index`, `None` for anonymous `?` parameters.
+    pub fn bind_parameter_name(&self, index: usize) -> Option<&str> {
+        unsafe {
+            let name = sqlite3_bind_parameter_name(self.0.as_ptr(), index as c_int);
+            if name.is_null() {
+                return None;
+            }
+            CStr::from_ptr(name).to_str().ok()
+        }
+    }
+
+    fn bind_status(&self, status: c_int) -> Result<(), SqliteError> {
+        if status != SQLITE_OK {
+            return Err(SqliteError::new(self.db_handle()));
+        }
+        Ok(())
+    }
+
+    pub fn bind_null(&mut self, index: usize) -> Result<(), SqliteError> {
+        self.bind_status(unsafe { sqlite3_bind_null(self.0.as_ptr(), index as c_int) })
+    }
+
+    pub fn bind_int(&mut self, index: usize, value: i32) -> Result<(), SqliteError> {
+        self.bind_status(unsafe { sqlite3_bind_int(self.0.as_ptr(), index as c_int, value) })
+    }
+
+    pub fn bind_int64(&mut self, index: usize, value: i64) -> Result<(), SqliteError> {
+        self.bind_status(unsafe { sqlite3_bind_int64(self.0.as_ptr(), index as c_int, value) })
+    }
+
+    pub fn bind_double(&mut self, index: usize, value: f64) -> Result<(), SqliteError> {
+        self.bind_status(unsafe { sqlite3_bind_double(self.0.as_ptr(), index as c_int, value) })
+    }
+
+    pub fn bind_text(&mut self, index: usize, value: &str) -> Result<(), SqliteError> {
+        self.bind_status(unsafe {
+            sqlite3_bind_text(
+                self.0.as_ptr(),
+                index as c_int,
+                value.as_ptr() as *const c_char,
+                value.len() as c_int,
+                SQLITE_TRANSIENT(),
+            )
+        })
+    }
+
+    pub fn bind_blob(&mut self, index: usize, value: &[u8]) -> Result<(), SqliteError> {
+        self.bind_status(unsafe {
+            sqlite3_bind_blob(
+                self.0.as_ptr(),
+                index as c_int,
+                value.as_ptr() as *const _,
+                value.len() as c_int,
+                SQLITE_TRANSIENT(),
+            )
+        })
+    }
+
     pub fn column_count(&self) -> usize {
         unsafe { sqlite3_column_count(self.0.as_ptr()) as usize }
     }
 
-    pub fn column_type(&self, index: usize) -> ColumnType {
-        let type_code = unsafe { sqlite3_column_type(self.0.as_ptr(), index as c_int) };
-        ColumnType::from_type_code(type_code)
+    /// Name of the result column, the alias given with `AS` if there is one.
+    pub fn column_name(&self, index: usize) -> Option<&str> {
+        unsafe {
+            let name = sqlite3_column_name(self.0.as_ptr(), index as c_int);
+            if name.is_null() {
+                return None;
+            }
+            CStr::from_ptr(name).to_str().ok()
+        }
+    }
+
+    /// Declared type of the table column the result column comes from.
+    pub fn column_decltype(&self, index: usize) -> Option<&str> {
+        unsafe {
+            let decltype = sqlite3_column_decltype(self.0.as_ptr(), index as c_int);
+            if decltype.is_null() {
+                return None;
+            }
+            CStr::from_ptr(decltype).to_str().ok()
+        }
+    }
+
+    /// Database, table and column the result column comes from, `None` for expressions.
+    pub fn column_origin(&self, index: usize) -> Option<ColumnOrigin> {
+        let (db_name, table_name, origin_name) = self.column_origin_ptrs(index)?;
+        unsafe {
+            Some(ColumnOrigin {
+                database: CStr::from_ptr(db_name).to_str().ok()?.to_string(),
+                table: CStr::from_ptr(table_name).to_str().ok()?.to_string(),
+                column: CStr::from_ptr(origin_name).to_str().ok()?.to_string(),
+            })
+        }
     }
 
-    pub fn column_database_type(&self, index: usize) -> anyhow::Result<Option<ColumnType>> {
+    fn column_origin_ptrs(
+        &self,
+        index: usize,
+    ) -> Option<(*const c_char, *const c_char, *const c_char)> {
         unsafe {
             // https://sqlite.org/c3ref/column_database_name.html
             //
@@ -62,9 +156,22 @@ impl Statement {
             let origin_name = sqlite3_column_origin_name(self.0.as_ptr(), index as c_int);
 
             if db_name.is_null() || table_name.is_null() || origin_name.is_null() {
-                return Ok(None);
+                return None;
             }
+            Some((db_name, table_name, origin_name))
+        }
+    }
 
+    pub fn column_type(&self, index: usize) -> ColumnType {
+        let type_code = unsafe { sqlite3_column_type(self.0.as_ptr(), index as c_int) };
+        ColumnType::from_type_code(type_code)
+    }
+
+    pub fn column_database_type(&self, index: usize) -> Result<Option<ColumnType>, ExplainerError> {
+        let Some((db_name, table_name, origin_name)) = self.column_origin_ptrs(index) else {
+            return Ok(None);
+        };
+        unsafe {
             let mut not_null: c_int = 0;
             let mut datatype = std::ptr::null();
 
@@ -160,6 +267,25 @@ mod tests {
                 nullable: Some(true),
             }
         );
+        let stmt = conn.prepare(cstr!("SELECT * FROM kv WHERE key = :key OR value = ?5"))?;
+        assert_eq!(stmt.bind_parameter_count(), 5);
+        assert_eq!(stmt.bind_parameter_name(1), Some(":key"));
+        assert_eq!(stmt.bind_parameter_name(2), None);
+        assert_eq!(stmt.bind_parameter_name(5), Some("?5"));
+        let stmt = conn.prepare(cstr!("SELECT key AS k, value + 1 FROM kv"))?;
+        assert_eq!(stmt.column_name(0), Some("k"));
+        assert_eq!(stmt.column_name(1), Some("value + 1"));
+        assert_eq!(stmt.column_decltype(0), Some("bigint"));
+        assert_eq!(stmt.column_decltype(1), None);
+        assert_eq!(
+            stmt.column_origin(0),
+            Some(ColumnOrigin {
+                database: "main".to_string(),
+                table: "kv".to_string(),
+                column: "key".to_string(),
+            })
+        );
+        assert_eq!(stmt.column_origin(1), None);
         let mut stmt = conn.prepare(cstr!("pragma table_info(kv)"))?;
         let _ = stmt.step();
         assert_eq!(stmt.column_count(), 6);
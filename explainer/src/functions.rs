@@ -0,0 +1,536 @@
+//! Return types of SQL functions.
+//!
+//! SQLite's built-in scalar functions are known, functions an application defines
+//! itself are declared with [`FunctionSignatures`].
+//!
+//! `OP_FUNCTION` shows the function it calls in P4 as `name(argc)`, with an `argc` of
+//! `-1` for functions taking any number of arguments; how many such a call passes is in
+//! the comment of the instruction. `coalesce`, `ifnull`, `iif`, `likely`, `unlikely`
+//! and `likelihood` never show up there, they are compiled to plain opcodes.
+
+use std::{collections::HashMap, fmt};
+
+use crate::types::{ColumnType, DataType};
+
+use DataType::{BigInt, Blob, Bool, Int, Null, Real, Text};
+use Nullable::{IfArgNull, Maybe, Never};
+use Returns::{AnyArg, Arg, Numeric, TextOrBlob, Type};
+
+#[derive(Debug, Clone, Copy)]
+enum Returns {
+    Type(DataType),
+    /// The type of the argument at this index.
+    Arg(usize),
+    /// The type of the first argument of a known type.
+    AnyArg,
+    /// BLOB if the first argument is a BLOB, TEXT otherwise.
+    TextOrBlob,
+    /// BIGINT if the first argument is an integer, REAL if it is a real.
+    Numeric,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Nullable {
+    Never,
+    Maybe,
+    /// NULL if any argument is NULL.
+    IfArgNull,
+}
+
+// (name, number of arguments or -1 for any, return type, nullability)
+#[rustfmt::skip]
+const BUILTINS: &[(&str, i32, Returns, Nullable)] = &[
+    // core functions
+    ("abs", 1, Arg(0), IfArgNull),
+    ("changes", 0, Type(BigInt), Never),
+    ("char", -1, Type(Text), Never),
+    ("concat", -1, Type(Text), Never),
+    ("concat_ws", -1, Type(Text), IfArgNull),
+    ("format", -1, Type(Text), Maybe),
+    ("glob", 2, Type(Bool), IfArgNull),
+    ("hex", 1, Type(Text), Never),
+    ("instr", 2, Type(Int), IfArgNull),
+    ("last_insert_rowid", 0, Type(BigInt), Never),
+    ("length", 1, Type(Int), IfArgNull),
+    ("like", 2, Type(Bool), IfArgNull),
+    ("like", 3, Type(Bool), IfArgNull),
+    ("lower", 1, Type(Text), IfArgNull),
+    ("ltrim", 1, Type(Text), IfArgNull),
+    ("ltrim", 2, Type(Text), IfArgNull),
+    ("max", -1, AnyArg, IfArgNull),
+    ("min", -1, AnyArg, IfArgNull),
+    ("nullif", 2, Arg(0), Maybe),
+    ("octet_length", 1, Type(Int), IfArgNull),
+    ("printf", -1, Type(Text), Maybe),
+    ("quote", 1, Type(Text), Never),
+    ("random", 0, Type(BigInt), Never),
+    ("randomblob", 1, Type(Blob), Never),
+    ("replace", 3, Type(Text), IfArgNull),
+    ("round", 1, Type(Real), IfArgNull),
+    ("round", 2, Type(Real), IfArgNull),
+    ("rtrim", 1, Type(Text), IfArgNull),
+    ("rtrim", 2, Type(Text), IfArgNull),
+    ("sign", 1, Type(Int), Maybe),
+    ("soundex", 1, Type(Text), Never),
+    ("sqlite_source_id", 0, Type(Text), Never),
+    ("sqlite_version", 0, Type(Text), Never),
+    ("substr", 2, TextOrBlob, IfArgNull),
+    ("substr", 3, TextOrBlob, IfArgNull),
+    ("substring", 2, TextOrBlob, IfArgNull),
+    ("substring", 3, TextOrBlob, IfArgNull),
+    ("total_changes", 0, Type(BigInt), Never),
+    ("trim", 1, Type(Text), IfArgNull),
+    ("trim", 2, Type(Text), IfArgNull),
+    ("typeof", 1, Type(Text), Never),
+    ("unhex", 1, Type(Blob), Maybe),
+    ("unhex", 2, Type(Blob), Maybe),
+    ("unicode", 1, Type(Int), Maybe),
+    ("upper", 1, Type(Text), IfArgNull),
+    ("zeroblob", 1, Type(Blob), Never),
+    // date and time functions, NULL for invalid dates
+    ("current_date", 0, Type(Text), Never),
+    ("current_time", 0, Type(Text), Never),
+    ("current_timestamp", 0, Type(Text), Never),
+    ("date", -1, Type(Text), Maybe),
+    ("datetime", -1, Type(Text), Maybe),
+    ("julianday", -1, Type(Real), Maybe),
+    ("strftime", -1, Type(Text), Maybe),
+    ("time", -1, Type(Text), Maybe),
+    ("timediff", 2, Type(Text), Maybe),
+    ("unixepoch", -1, Type(BigInt), Maybe),
+    // math functions, NULL outside of their domain
+    ("acos", 1, Type(Real), Maybe),
+    ("acosh", 1, Type(Real), Maybe),
+    ("asin", 1, Type(Real), Maybe),
+    ("asinh", 1, Type(Real), Maybe),
+    ("atan", 1, Type(Real), Maybe),
+    ("atan2", 2, Type(Real), Maybe),
+    ("atanh", 1, Type(Real), Maybe),
+    ("ceil", 1, Arg(0), IfArgNull),
+    ("ceiling", 1, Arg(0), IfArgNull),
+    ("cos", 1, Type(Real), Maybe),
+    ("cosh", 1, Type(Real), Maybe),
+    ("degrees", 1, Type(Real), Maybe),
+    ("exp", 1, Type(Real), Maybe),
+    ("floor", 1, Arg(0), IfArgNull),
+    ("ln", 1, Type(Real), Maybe),
+    ("log", 1, Type(Real), Maybe),
+    ("log", 2, Type(Real), Maybe),
+    ("log10", 1, Type(Real), Maybe),
+    ("log2", 1, Type(Real), Maybe),
+    ("mod", 2, Type(Real), Maybe),
+    ("pi", 0, Type(Real), Never),
+    ("pow", 2, Type(Real), Maybe),
+    ("power", 2, Type(Real), Maybe),
+    ("radians", 1, Type(Real), Maybe),
+    ("sin", 1, Type(Real), Maybe),
+    ("sinh", 1, Type(Real), Maybe),
+    ("sqrt", 1, Type(Real), Maybe),
+    ("tan", 1, Type(Real), Maybe),
+    ("tanh", 1, Type(Real), Maybe),
+    ("trunc", 1, Arg(0), IfArgNull),
+    // JSON functions, NULL for NULL or missing paths
+    ("json", 1, Type(Text), Maybe),
+    ("json_array", -1, Type(Text), Never),
+    ("json_array_length", 1, Type(Int), Maybe),
+    ("json_array_length", 2, Type(Int), Maybe),
+    ("json_extract", -1, Type(Null), Maybe),
+    ("->", 2, Type(Text), Maybe),
+    ("->>", 2, Type(Null), Maybe),
+    ("json_insert", -1, Type(Text), Maybe),
+    ("json_object", -1, Type(Text), Never),
+    ("json_patch", 2, Type(Text), Maybe),
+    ("json_quote", 1, Type(Text), Never),
+    ("json_remove", -1, Type(Text), Maybe),
+    ("json_replace", -1, Type(Text), Maybe),
+    ("json_set", -1, Type(Text), Maybe),
+    ("json_type", 1, Type(Text), Maybe),
+    ("json_type", 2, Type(Text), Maybe),
+    ("json_valid", 1, Type(Bool), IfArgNull),
+];
+
+// (name, number of arguments, return type and nullability over at least one row,
+// nullability over no rows)
+#[rustfmt::skip]
+const BUILTIN_AGGREGATES: &[(&str, i32, Returns, Nullable, Nullable)] = &[
+    ("avg", 1, Type(Real), IfArgNull, Maybe),
+    ("count", 0, Type(BigInt), Never, Never),
+    ("count", 1, Type(BigInt), Never, Never),
+    ("group_concat", 1, Type(Text), IfArgNull, Maybe),
+    ("group_concat", 2, Type(Text), IfArgNull, Maybe),
+    ("json_group_array", 1, Type(Text), Never, Never),
+    ("json_group_object", 2, Type(Text), Never, Never),
+    ("max", 1, Arg(0), IfArgNull, Maybe),
+    ("min", 1, Arg(0), IfArgNull, Maybe),
+    ("string_agg", 2, Type(Text), IfArgNull, Maybe),
+    ("sum", 1, Numeric, IfArgNull, Maybe),
+    ("total", 1, Type(Real), Never, Never),
+    // window functions, NULL past the edges of the partition or frame
+    ("cume_dist", 0, Type(Real), Never, Never),
+    ("dense_rank", 0, Type(BigInt), Never, Never),
+    ("first_value", 1, Arg(0), Maybe, Maybe),
+    ("lag", 1, Arg(0), Maybe, Maybe),
+    ("lag", 2, Arg(0), Maybe, Maybe),
+    ("lag", 3, AnyArg, Maybe, Maybe),
+    ("last_value", 1, Arg(0), Maybe, Maybe),
+    ("lead", 1, Arg(0), Maybe, Maybe),
+    ("lead", 2, Arg(0), Maybe, Maybe),
+    ("lead", 3, AnyArg, Maybe, Maybe),
+    ("nth_value", 2, Arg(0), Maybe, Maybe),
+    ("ntile", 1, Type(BigInt), Never, Never),
+    ("percent_rank", 0, Type(Real), Never, Never),
+    ("rank", 0, Type(BigInt), Never, Never),
+    ("row_number", 0, Type(BigInt), Never, Never),
+];
+
+type ReturnTypeFn = dyn Fn(&[ColumnType]) -> ColumnType;
+
+struct Signature {
+    aggregate: bool,
+    return_type: Box<ReturnTypeFn>,
+}
+
+/// Return types of application-defined functions, keyed by name and number of arguments.
+///
+/// An `argc` of `-1` declares a function taking any number of arguments; the types of
+/// all arguments passed are given to a return type closure, or only the first one when
+/// SQLite doesn't show how many were passed. Declared functions take precedence over
+/// the built-in ones.
+#[derive(Default)]
+pub struct FunctionSignatures {
+    functions: HashMap<(String, i32), Signature>,
+}
+
+impl fmt::Debug for FunctionSignatures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set()
+            .entries(
+                self.functions
+                    .keys()
+                    .map(|(name, argc)| format!("{name}({argc})")),
+            )
+            .finish()
+    }
+}
+
+impl FunctionSignatures {
+    pub fn new() -> FunctionSignatures {
+        FunctionSignatures::default()
+    }
+
+    fn insert(
+        &mut self,
+        name: &str,
+        argc: i32,
+        aggregate: bool,
+        f: Box<ReturnTypeFn>,
+    ) -> &mut Self {
+        // SQLite matches function names case-insensitively and shows them in lower case
+        let signature = Signature {
+            aggregate,
+            return_type: f,
+        };
+        self.functions
+            .insert((name.to_ascii_lowercase(), argc), signature);
+        self
+    }
+
+    /// Declare a scalar function returning `return_type`.
+    pub fn scalar(&mut self, name: &str, argc: i32, return_type: ColumnType) -> &mut Self {
+        self.insert(name, argc, false, Box::new(move |_| return_type))
+    }
+
+    /// Declare a scalar function whose return type depends on its argument types.
+    pub fn scalar_with(
+        &mut self,
+        name: &str,
+        argc: i32,
+        f: impl Fn(&[ColumnType]) -> ColumnType + 'static,
+    ) -> &mut Self {
+        self.insert(name, argc, false, Box::new(f))
+    }
+
+    /// Declare an aggregate function, which may also be used as a window function,
+    /// returning `return_type`.
+    pub fn aggregate(&mut self, name: &str, argc: i32, return_type: ColumnType) -> &mut Self {
+        self.insert(name, argc, true, Box::new(move |_| return_type))
+    }
+
+    /// Declare an aggregate function whose return type depends on its argument types.
+    pub fn aggregate_with(
+        &mut self,
+        name: &str,
+        argc: i32,
+        f: impl Fn(&[ColumnType]) -> ColumnType + 'static,
+    ) -> &mut Self {
+        self.insert(name, argc, true, Box::new(f))
+    }
+
+    /// Name, number of arguments and whether it is an aggregate, of every declared function.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i32, bool)> {
+        self.functions
+            .iter()
+            .map(|((name, argc), signature)| (name.as_str(), *argc, signature.aggregate))
+    }
+
+    /// Return type of the declared function called as `p4`, see [`return_type`]. Only
+    /// aggregates are looked up if `aggregate`, only scalar functions otherwise.
+    pub(crate) fn return_type(
+        &self,
+        p4: &str,
+        aggregate: bool,
+        passed: Option<usize>,
+        arg: impl Fn(usize) -> ColumnType,
+    ) -> Option<ColumnType> {
+        let (name, argc) = parse_function(p4)?;
+        let signature = self
+            .functions
+            .get(&(name.to_string(), argc))
+            .or_else(|| {
+                self.functions
+                    .get(&(name.to_string(), -1))
+                    .filter(|_| argc < 0)
+            })
+            .filter(|signature| signature.aggregate == aggregate)?;
+        Some((signature.return_type)(&arguments(argc, passed, arg)))
+    }
+}
+
+// a variadic function shows an `argc` of -1, if the number of arguments passed isn't
+// known either only rely on the first one
+fn arguments(
+    argc: i32,
+    passed: Option<usize>,
+    arg: impl Fn(usize) -> ColumnType,
+) -> Vec<ColumnType> {
+    let argc = match argc {
+        0.. => argc as usize,
+        _ => passed.unwrap_or(1),
+    };
+    (0..argc).map(arg).collect()
+}
+
+/// Return type of the scalar function called as `p4`, declared in `functions` or built in,
+/// with `passed` the number of arguments passed, if known, and `arg` giving the type of
+/// the argument at an index. `None` for unknown functions.
+pub(crate) fn return_type(
+    functions: &FunctionSignatures,
+    p4: &str,
+    passed: Option<usize>,
+    arg: impl Fn(usize) -> ColumnType,
+) -> Option<ColumnType> {
+    functions
+        .return_type(p4, false, passed, &arg)
+        .or_else(|| builtin_return_type(p4, passed, arg))
+}
+
+/// Number of arguments passed by an `OP_FUNCTION`, from its comment `r[P3]=func(r[P2..])`.
+/// `None` without a comment, when SQLite isn't built with `SQLITE_ENABLE_EXPLAIN_COMMENTS`.
+pub(crate) fn argument_count(comment: &str) -> Option<usize> {
+    let registers = comment.split_once("=func(")?.1.strip_suffix(')')?;
+    if registers.is_empty() {
+        return Some(0);
+    }
+    let registers = registers.strip_prefix("r[")?.strip_suffix(']')?;
+    match registers.split_once("..") {
+        Some((first, last)) => {
+            let first: usize = first.parse().ok()?;
+            let last: usize = last.parse().ok()?;
+            Some(last.checked_sub(first)? + 1)
+        }
+        None => registers.parse::<usize>().ok().map(|_| 1),
+    }
+}
+
+/// Split the P4 of `OP_FUNCTION`, `name(argc)`, into its name and argument count.
+fn parse_function(p4: &str) -> Option<(&str, i32)> {
+    let (name, argc) = p4.strip_suffix(')')?.rsplit_once('(')?;
+    Some((name, argc.parse().ok()?))
+}
+
+/// Return type of the built-in function called as `p4`, with `passed` and `arg` as for
+/// [`return_type`]. `None` for functions that aren't built in.
+fn builtin_return_type(
+    p4: &str,
+    passed: Option<usize>,
+    arg: impl Fn(usize) -> ColumnType,
+) -> Option<ColumnType> {
+    let (name, argc) = parse_function(p4)?;
+    let &(_, _, returns, nullable) = BUILTINS
+        .iter()
+        .find(|&&(n, a, ..)| n == name && (a == argc || a < 0 && argc < 0))?;
+    // the variadic functions above all take at least one argument
+    let partial = argc < 0 && passed.is_none();
+    Some(evaluate(
+        returns,
+        nullable,
+        partial,
+        &arguments(argc, passed, arg),
+    ))
+}
+
+/// Type of the accumulator of the built-in aggregate called as `p4` after it has seen a
+/// row, with `arg` giving the type of the argument at an index.
+pub(crate) fn aggregate_step_type(
+    p4: &str,
+    arg: impl Fn(usize) -> ColumnType,
+) -> Option<ColumnType> {
+    let (name, argc) = parse_function(p4)?;
+    let &(_, _, returns, nullable, _) = BUILTIN_AGGREGATES
+        .iter()
+        .find(|&&(n, a, ..)| n == name && a == argc)?;
+    Some(evaluate(
+        returns,
+        nullable,
+        false,
+        &arguments(argc, None, arg),
+    ))
+}
+
+/// Result of the built-in aggregate called as `p4` over no rows, with `arg` giving the
+/// type of the argument at an index.
+pub(crate) fn aggregate_empty_type(
+    p4: &str,
+    arg: impl Fn(usize) -> ColumnType,
+) -> Option<ColumnType> {
+    let (name, argc) = parse_function(p4)?;
+    let &(_, _, returns, _, nullable) = BUILTIN_AGGREGATES
+        .iter()
+        .find(|&&(n, a, ..)| n == name && a == argc)?;
+    Some(evaluate(
+        returns,
+        nullable,
+        false,
+        &arguments(argc, None, arg),
+    ))
+}
+
+fn evaluate(
+    returns: Returns,
+    nullable: Nullable,
+    partial: bool,
+    args: &[ColumnType],
+) -> ColumnType {
+    let datatype = match returns {
+        Type(datatype) => datatype,
+        Arg(i) => args.get(i).map_or(Null, |arg| arg.datatype),
+        AnyArg => args
+            .iter()
+            .map(|arg| arg.datatype)
+            .find(|&datatype| datatype != Null)
+            .unwrap_or(Null),
+        TextOrBlob => match args.first() {
+            Some(ColumnType { datatype: Blob, .. }) => Blob,
+            _ => Text,
+        },
+        Numeric => match args.first().map(|arg| arg.datatype) {
+            Some(Int | BigInt | Bool) => BigInt,
+            Some(Real) => Real,
+            _ => Null,
+        },
+    };
+    // if `partial`, `args` may be missing some of the arguments passed
+    let nullable = match nullable {
+        Never => Some(false),
+        Maybe => Some(true),
+        IfArgNull if args.iter().any(|arg| arg.nullable == Some(true)) => Some(true),
+        IfArgNull if !partial && args.iter().all(|arg| arg.nullable == Some(false)) => Some(false),
+        IfArgNull => None,
+    };
+    ColumnType { datatype, nullable }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_return_type() {
+        let text = ColumnType {
+            datatype: Text,
+            nullable: Some(false),
+        };
+        let blob = ColumnType {
+            datatype: Blob,
+            nullable: Some(true),
+        };
+        let return_type = |p4, args: &[ColumnType]| {
+            builtin_return_type(p4, Some(args.len()), |i| {
+                args.get(i).copied().unwrap_or_default()
+            })
+            .map(|ty| (ty.datatype, ty.nullable))
+        };
+
+        assert_eq!(return_type("length(1)", &[text]), Some((Int, Some(false))));
+        assert_eq!(return_type("length(1)", &[blob]), Some((Int, Some(true))));
+        assert_eq!(return_type("substr(3)", &[blob]), Some((Blob, Some(true))));
+        assert_eq!(
+            return_type("substr(2)", &[text, text]),
+            Some((Text, Some(false)))
+        );
+        assert_eq!(return_type("datetime(-1)", &[]), Some((Text, Some(true))));
+        assert_eq!(return_type("max(-1)", &[text]), Some((Text, Some(false))));
+        assert_eq!(
+            return_type("max(-1)", &[text, blob]),
+            Some((Text, Some(true)))
+        );
+        assert_eq!(
+            builtin_return_type("max(-1)", None, |_| text).map(|ty| ty.nullable),
+            Some(None)
+        );
+        assert_eq!(return_type("max(-3)", &[blob]), Some((Blob, Some(true))));
+        assert_eq!(return_type("hex(1)", &[blob]), Some((Text, Some(false))));
+        assert_eq!(return_type("length(2)", &[text]), None);
+        assert_eq!(return_type("my_function(1)", &[text]), None);
+        assert_eq!(parse_function("->>(2)"), Some(("->>", 2)));
+        assert_eq!(argument_count("r[1]=func(r[2..4])"), Some(3));
+        assert_eq!(argument_count("r[1]=func(r[2])"), Some(1));
+        assert_eq!(argument_count("r[1]=func()"), Some(0));
+        assert_eq!(argument_count(""), None);
+    }
+
+    #[test]
+    fn test_function_signatures() {
+        let int = ColumnType {
+            datatype: Int,
+            nullable: Some(false),
+        };
+        let mut functions = FunctionSignatures::new();
+        functions
+            .scalar("length", 1, int)
+            .scalar_with("Pick", -1, |args| args[0])
+            .scalar_with("last", -1, |args| args[args.len() - 1])
+            .aggregate("weight", 1, int);
+        let text = |_| ColumnType {
+            datatype: Text,
+            nullable: Some(true),
+        };
+
+        // declared functions take precedence over built-in ones
+        assert_eq!(return_type(&functions, "length(1)", None, text), Some(int));
+        assert_eq!(
+            return_type(&functions, "pick(-1)", None, text),
+            Some(text(0))
+        );
+        assert_eq!(return_type(&functions, "pick(2)", None, text), None);
+        assert_eq!(
+            return_type(&functions, "upper(1)", None, text).map(|ty| ty.datatype),
+            Some(Text)
+        );
+
+        // all the arguments passed to a variadic function
+        let third_int = |i| if i == 2 { int } else { text(i) };
+        assert_eq!(
+            return_type(&functions, "last(-1)", Some(3), third_int),
+            Some(int)
+        );
+
+        // aggregates aren't called by OP_FUNCTION, nor scalar functions by OP_AGG_STEP
+        assert_eq!(return_type(&functions, "weight(1)", None, text), None);
+        assert_eq!(
+            functions.return_type("weight(1)", true, None, text),
+            Some(int)
+        );
+        assert_eq!(functions.return_type("length(1)", true, None, text), None);
+    }
+}
@@ -1,68 +1,244 @@
-use std::ffi::{CStr, CString};
+use std::ffi::CStr;
 
-use ffi::connection::Connection;
-use types::StatementInfo;
+use analyzer::Analyzer;
+use error::ExplainerError;
+use types::{ScriptStatement, StatementInfo};
 
+pub mod analyzer;
+pub mod codegen;
+pub mod error;
 pub mod explain;
 pub mod ffi;
+pub mod functions;
+#[cfg(feature = "offline")]
+pub mod offline;
+pub mod query;
 pub mod utils;
 
 pub mod types;
 
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+pub fn get_statement_info(db_path: &CStr, sql: &str) -> Result<StatementInfo, ExplainerError> {
+    Analyzer::open(db_path)?.statement_info(sql)
 }
 
-pub fn get_statement_info(db_path: &CStr, sql: &str) -> anyhow::Result<StatementInfo> {
-    let conn = Connection::establish(db_path)?;
-    let sql_c = {
-        let mut sql = sql.to_string();
-        sql.push('\0');
-        CString::from_vec_with_nul(sql.into_bytes())?
-    };
-    let stmt = conn.prepare(&sql_c)?;
-    let read_only = stmt.read_only();
-    let parameter_count = stmt.bind_parameter_count();
-
-    // output types
-    let column_count = stmt.column_count();
-    let mut has_undecided_datatype = false;
-
-    // t1: get types from db directly
-    let mut column_types = (0..column_count)
-        .map(|i| {
-            let column_type = stmt.column_database_type(i)?;
-            if column_type.is_none() {
-                has_undecided_datatype = true;
-            }
-            Ok(column_type)
-        })
-        .collect::<anyhow::Result<Vec<_>>>()?;
-    // t2: get types from explain
-    if has_undecided_datatype {
-        let explain_column_types = explain::explain(&conn, sql)?;
-        for (i, column_type) in column_types.iter_mut().enumerate() {
-            if column_type.is_none() {
-                *column_type = explain_column_types.get(i).cloned();
-            }
-        }
-    }
+/// Get the [`StatementInfo`] of `sql` against the schema created by running each of the
+/// `schema` scripts, in order, on an empty in-memory database.
+pub fn get_statement_info_with_schema<S: AsRef<str>>(
+    schema: &[S],
+    sql: &str,
+) -> Result<StatementInfo, ExplainerError> {
+    Analyzer::with_schema(schema)?.statement_info(sql)
+}
 
-    Ok(StatementInfo {
-        read_only,
-        input_length: parameter_count,
-        output_length: column_count,
-        output_types: column_types,
-    })
+/// Get the [`StatementInfo`] of every statement in a script of `;` separated statements.
+///
+/// The database is opened read-only, so a statement can't refer to tables created by an
+/// earlier statement in the same script. [`get_script_info_with_schema`] and
+/// [`Analyzer::script_info`] on a writable connection can.
+pub fn get_script_info(db_path: &CStr, sql: &str) -> Result<Vec<ScriptStatement>, ExplainerError> {
+    Analyzer::open(db_path)?.script_info(sql)
+}
+
+/// Like [`get_script_info`], against the schema created by running each of the
+/// `schema` scripts, in order, on an empty in-memory database. Statements can refer to
+/// tables created earlier in `sql`, see [`Analyzer::script_info`].
+pub fn get_script_info_with_schema<S: AsRef<str>>(
+    schema: &[S],
+    sql: &str,
+) -> Result<Vec<ScriptStatement>, ExplainerError> {
+    Analyzer::with_schema(schema)?.script_info(sql)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ffi::error::{ErrorCode, ExtendedCode};
+    use crate::types::{ColumnType, DataType};
+
+    #[test]
+    fn test_parameters() -> anyhow::Result<()> {
+        let parameters = |sql| -> anyhow::Result<Vec<_>> {
+            Ok(get_statement_info(cstr!(":memory:"), sql)?
+                .parameters
+                .iter()
+                .map(|p| (p.index, p.name.clone(), p.repeated, p.gap))
+                .collect())
+        };
+        let name = |name: &str| Some(name.to_string());
+        assert_eq!(
+            parameters("SELECT :a, ?, @b, :a, ?5")?,
+            vec![
+                (1, name(":a"), true, false),
+                (2, None, false, false),
+                (3, name("@b"), false, false),
+                (4, None, false, true),
+                (5, name("?5"), false, false),
+            ]
+        );
+        // ?01 and ?1 are the same parameter
+        assert_eq!(
+            parameters("SELECT ?01, ?1, ?3, ?")?,
+            vec![
+                (1, name("?01"), true, false),
+                (2, None, false, true),
+                (3, name("?3"), false, false),
+                (4, None, false, false),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_info() -> anyhow::Result<()> {
+        let sql = "-- first\nSELECT 1;\n\n/* second */ SELECT :a, 'x;y' ;\n-- trailing\n";
+        let statements = get_script_info(cstr!(":memory:"), sql)?;
+        assert_eq!(statements.len(), 2);
+        assert_eq!(&sql[statements[0].span.clone()], "SELECT 1;");
+        assert_eq!(statements[0].info.input_length, 0);
+        assert_eq!(&sql[statements[1].span.clone()], "SELECT :a, 'x;y' ;");
+        assert_eq!(statements[1].info.input_length, 1);
+        assert_eq!(statements[1].info.output_length, 2);
+        Ok(())
+    }
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_script_spans() -> anyhow::Result<()> {
+        // the last statement has no `;`, its span stops before the trailing whitespace
+        let sql = "SELECT 1 ;  \n\tSELECT 2  \n\n";
+        let statements = get_script_info(cstr!(":memory:"), sql)?;
+        let spans: Vec<_> = statements.iter().map(|s| &sql[s.span.clone()]).collect();
+        assert_eq!(spans, vec!["SELECT 1 ;", "SELECT 2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_info_migration() -> anyhow::Result<()> {
+        let sql = "CREATE TABLE t(id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+            CREATE INDEX t_name ON t(name);
+            INSERT INTO t(name) VALUES (?) RETURNING id;
+            ALTER TABLE t ADD COLUMN email TEXT;
+            SELECT name, email FROM t;
+            DROP TABLE t;";
+        let statements = get_script_info_with_schema(&[""; 0], sql)?;
+        assert_eq!(statements.len(), 6);
+        assert_eq!(
+            statements[4].info.output_types,
+            vec![
+                Some(ColumnType {
+                    datatype: DataType::Text,
+                    nullable: Some(false),
+                }),
+                Some(ColumnType {
+                    datatype: DataType::Text,
+                    nullable: Some(true),
+                }),
+            ]
+        );
+
+        // the schema changes are rolled back, also when the script fails
+        let mut analyzer = Analyzer::with_schema(&[""; 0])?;
+        analyzer.script_info(sql)?;
+        assert!(analyzer
+            .script_info("CREATE TABLE t(a); SELECT b FROM t")
+            .is_err());
+        assert!(analyzer.statement_info("SELECT * FROM t").is_err());
+        analyzer.script_info("CREATE TABLE t(a)")?;
+
+        // a read-only database only prepares them
+        let error =
+            get_script_info(cstr!(":memory:"), "CREATE TABLE t(a); SELECT a FROM t").unwrap_err();
+        assert!(error.to_string().starts_with("no such table: t"), "{error}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_statement_info_with_schema() -> anyhow::Result<()> {
+        let schema = [
+            "CREATE TABLE users(id INTEGER PRIMARY KEY, name TEXT NOT NULL);",
+            "ALTER TABLE users ADD COLUMN email TEXT; CREATE INDEX users_email ON users(email);",
+        ];
+        let info = get_statement_info_with_schema(&schema, "SELECT name, email FROM users")?;
+        assert_eq!(
+            info.output_types,
+            vec![
+                Some(ColumnType {
+                    datatype: DataType::Text,
+                    nullable: Some(false),
+                }),
+                Some(ColumnType {
+                    datatype: DataType::Text,
+                    nullable: Some(true),
+                }),
+            ]
+        );
+
+        let statements =
+            get_script_info_with_schema(&schema, "DELETE FROM users; SELECT id FROM users;")?;
+        assert_eq!(statements.len(), 2);
+        assert!(!statements[0].info.read_only);
+        assert!(statements[1].info.read_only);
+
+        assert!(get_statement_info_with_schema(&schema, "SELECT * FROM missing").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepare_error() {
+        let err = get_statement_info(cstr!(":memory:"), "SELECT * FORM t").unwrap_err();
+        assert!(matches!(
+            err,
+            ExplainerError::Prepare {
+                code: ErrorCode::Unknown,
+                extended_code: ExtendedCode {
+                    extended_code: libsqlite3_sys::SQLITE_ERROR,
+                    ..
+                },
+                offset: Some(9),
+                ..
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "near \"FORM\": syntax error (SQL error or missing database, code 1)"
+        );
+        assert!(matches!(
+            get_script_info(cstr!(":memory:"), "SELECT 1;\nSELECT nope(1);"),
+            Err(ExplainerError::Prepare {
+                offset: Some(17),
+                ..
+            })
+        ));
+        assert!(matches!(
+            get_statement_info(cstr!(":memory:"), "SELECT 1\0"),
+            Err(ExplainerError::InteriorNul(8))
+        ));
+    }
+
+    #[test]
+    fn test_returning() -> anyhow::Result<()> {
+        let schema =
+            ["CREATE TABLE users(id INTEGER PRIMARY KEY, name TEXT NOT NULL, email TEXT);"];
+        let text = |nullable| {
+            Some(ColumnType {
+                datatype: DataType::Text,
+                nullable: Some(nullable),
+            })
+        };
+
+        let info = get_statement_info_with_schema(
+            &schema,
+            "INSERT INTO users(name, email) VALUES (?, ?) RETURNING name, email",
+        )?;
+        assert!(!info.read_only);
+        assert_eq!(info.output_types, vec![text(false), text(true)]);
+        assert_eq!(info.input_types, vec![text(false), text(true)]);
+
+        let info = get_statement_info_with_schema(
+            &schema,
+            "UPDATE users SET email = lower(name) WHERE id = ? RETURNING email || ''",
+        )?;
+        assert!(!info.read_only);
+        assert_eq!(info.output_types, vec![text(false)]);
+        Ok(())
     }
 }
@@ -0,0 +1,322 @@
+use std::{
+    ffi::CString,
+    fmt::Write as _,
+    io::{self, Read},
+    process::ExitCode,
+};
+
+use anyhow::{anyhow, bail, Context};
+use explainer::{
+    analyzer::Analyzer,
+    types::{ColumnType, ScriptStatement},
+};
+
+const USAGE: &str = "\
+Usage: explainer [OPTIONS] [SQL]...
+
+Print the input and output types of SQL statements.
+
+SQL is read from the arguments, from --file, or from stdin when neither is given
+or for a `-` argument. Arguments after `--` are SQL, even when they start with `-`.
+Each input may hold several `;` separated statements.
+
+Options:
+  -d, --database <PATH>  Analyse against the database at PATH, opened read-only
+  -s, --schema <FILE>    Analyse against an in-memory database created by running
+                         the schema script FILE (may be repeated, applied in order)
+  -f, --file <FILE>      Read SQL from FILE (may be repeated)
+      --format <FORMAT>  Output format, `json` (default) or `table`
+      --safe             Fail on pragmas that set a value, rather than run them as
+                         they are analysed
+  -h, --help             Print this help
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Table,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Input {
+    Sql(String),
+    /// A file, or stdin for `-`.
+    File(String),
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    database: Option<String>,
+    schema: Vec<String>,
+    inputs: Vec<Input>,
+    format: Option<Format>,
+    safe: bool,
+    help: bool,
+}
+
+/// A statement of the output, with the SQL it spans.
+#[derive(serde::Serialize)]
+struct OutputStatement<'a> {
+    sql: &'a str,
+    #[serde(flatten)]
+    statement: &'a ScriptStatement,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> anyhow::Result<Args> {
+    let mut parsed = Args::default();
+    let mut options = true;
+    while let Some(arg) = args.next() {
+        if !options || arg == "-" {
+            parsed.inputs.push(match arg == "-" {
+                true => Input::File(arg),
+                false => Input::Sql(arg),
+            });
+            continue;
+        }
+        let mut value = |name: &str| {
+            args.next()
+                .with_context(|| format!("missing value for `{name}`"))
+        };
+        match &*arg {
+            "-d" | "--database" => parsed.database = Some(value(&arg)?),
+            "-s" | "--schema" => parsed.schema.push(value(&arg)?),
+            "-f" | "--file" => parsed.inputs.push(Input::File(value(&arg)?)),
+            "--format" => {
+                parsed.format = Some(match &*value(&arg)? {
+                    "json" => Format::Json,
+                    "table" => Format::Table,
+                    format => bail!("unknown format: `{format}`"),
+                })
+            }
+            "--safe" => parsed.safe = true,
+            "-h" | "--help" => parsed.help = true,
+            "--" => options = false,
+            _ if arg.starts_with('-') => bail!("unknown option: `{arg}`"),
+            _ => parsed.inputs.push(Input::Sql(arg)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn run(args: Args) -> anyhow::Result<String> {
+    let mut analyzer = match (&args.database, args.schema.is_empty()) {
+        (Some(_), false) => bail!("`--database` and `--schema` can't be used together"),
+        (Some(path), true) => Analyzer::open(&CString::new(path.as_str())?)?,
+        (None, false) => {
+            let schema = args
+                .schema
+                .iter()
+                .map(|file| {
+                    std::fs::read_to_string(file)
+                        .with_context(|| format!("failed to read `{file}`"))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            Analyzer::with_schema(&schema)?
+        }
+        (None, true) => bail!("one of `--database` or `--schema` is required"),
+    };
+    analyzer.set_safe(args.safe);
+
+    let mut args_inputs = args.inputs;
+    if args_inputs.is_empty() {
+        args_inputs.push(Input::File("-".to_string()));
+    }
+    let mut inputs = Vec::new();
+    for input in args_inputs {
+        inputs.push(match input {
+            Input::Sql(sql) => sql,
+            Input::File(file) if file == "-" => {
+                let mut sql = String::new();
+                io::stdin()
+                    .read_to_string(&mut sql)
+                    .context("failed to read stdin")?;
+                sql
+            }
+            Input::File(file) => std::fs::read_to_string(&file)
+                .with_context(|| format!("failed to read `{file}`"))?,
+        });
+    }
+
+    let mut statements = Vec::new();
+    for sql in &inputs {
+        let script = analyzer
+            .script_info(sql)
+            .map_err(|e| anyhow!(e.render(sql)))?;
+        for statement in script {
+            statements.push((&sql[statement.span.clone()], statement));
+        }
+    }
+
+    Ok(match args.format.unwrap_or(Format::Json) {
+        Format::Json => serde_json::to_string(
+            &statements
+                .iter()
+                .map(|(sql, statement)| OutputStatement { sql, statement })
+                .collect::<Vec<_>>(),
+        )?,
+        Format::Table => table::statements(&statements),
+    })
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) if args.help => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Ok(args) => args,
+        Err(e) => {
+            eprint!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(args) {
+        Ok(output) => {
+            println!("{output}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+mod table {
+    use super::*;
+
+    fn column_type(ty: Option<&ColumnType>) -> String {
+        match ty {
+            Some(ColumnType {
+                datatype,
+                nullable: Some(nullable),
+            }) => format!("{datatype:?}{}", if *nullable { "?" } else { "" }),
+            Some(ColumnType {
+                datatype,
+                nullable: None,
+            }) => format!("{datatype:?} (nullability unknown)"),
+            None => "unknown".to_string(),
+        }
+    }
+
+    pub(super) fn statements(statements: &[(&str, ScriptStatement)]) -> String {
+        let mut out = String::new();
+        for (i, (sql, statement)) in statements.iter().enumerate() {
+            let info = &statement.info;
+            if i > 0 {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "{sql}");
+            let _ = writeln!(out, "  read only: {}", info.read_only);
+            let _ = writeln!(out, "  parameters: {}", info.input_length);
+            for (parameter, ty) in info.parameters.iter().zip(&info.input_types) {
+                let _ = writeln!(
+                    out,
+                    "    {:<4} {:<16} {}",
+                    parameter.index,
+                    match (&parameter.name, parameter.gap) {
+                        (Some(name), _) => name,
+                        (None, true) => "(gap)",
+                        (None, false) => "?",
+                    },
+                    column_type(ty.as_ref())
+                );
+            }
+            let _ = writeln!(out, "  columns: {}", info.output_length);
+            for (column, ty) in info.columns.iter().zip(&info.output_types) {
+                let origin = column
+                    .origin
+                    .as_ref()
+                    .map(|o| format!("{}.{}.{}", o.database, o.table, o.column))
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "    {:<20} {:<24} {}",
+                    column.name,
+                    column_type(ty.as_ref()),
+                    origin
+                );
+            }
+        }
+        out.truncate(out.trim_end().len());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Args {
+        parse_args(args.iter().map(|s| s.to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_json_output() {
+        let output = run(args(&[
+            "--schema",
+            "/dev/null",
+            "SELECT 1 AS \"a\"\"b\", ?;",
+        ]))
+        .unwrap();
+        assert_eq!(
+            output,
+            concat!(
+                r#"[{"sql":"SELECT 1 AS \"a\"\"b\", ?;","span":{"start":0,"end":22},"info":{"#,
+                r#""read_only":true,"input_length":1,"input_types":[null],"#,
+                r#""parameters":[{"index":1,"name":null,"repeated":false,"gap":false}],"#,
+                r#""output_length":2,"output_types":[{"datatype":"Int","nullable":false},"#,
+                r#"{"datatype":"Null","nullable":true}],"#,
+                r#""columns":[{"name":"a\"b","declared_type":null,"origin":null},"#,
+                r#"{"name":"?","declared_type":null,"origin":null}]}}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_inputs() {
+        let parsed = args(&["-s", "/dev/null", "SELECT 1", "-", "--", "-- name: x", "-f"]);
+        assert_eq!(
+            parsed.inputs,
+            vec![
+                Input::Sql("SELECT 1".into()),
+                Input::File("-".into()),
+                Input::Sql("-- name: x".into()),
+                Input::Sql("-f".into()),
+            ]
+        );
+        assert_eq!(parsed.schema, vec!["/dev/null".to_string()]);
+        assert_eq!(
+            args(&["-f", "-", "--format", "table"]).inputs,
+            vec![Input::File("-".into())]
+        );
+        let output = run(args(&["-s", "/dev/null", "--", "-- name: x\nSELECT 1"])).unwrap();
+        assert!(output.starts_with(r#"[{"sql":"SELECT 1","#), "{output}");
+    }
+
+    #[test]
+    fn test_bad_args() {
+        assert!(parse_args(["--format", "xml"].iter().map(|s| s.to_string())).is_err());
+        assert!(parse_args(["--database"].iter().map(|s| s.to_string())).is_err());
+        assert!(run(args(&["SELECT 1"])).is_err());
+    }
+
+    #[test]
+    fn test_safe() {
+        let sql = "PRAGMA recursive_triggers = ON";
+        assert!(run(args(&["-s", "/dev/null", sql])).is_ok());
+        assert!(run(args(&["-s", "/dev/null", "--safe", sql])).is_err());
+    }
+
+    #[test]
+    fn test_error_location() {
+        let error = run(args(&[
+            "--schema",
+            "/dev/null",
+            "SELECT 1;\nSELECT * FORM t;",
+        ]))
+        .unwrap_err()
+        .to_string();
+        assert!(error.ends_with(" --> 2:10\n  |\n2 | SELECT * FORM t;\n  |          ^"));
+    }
+}
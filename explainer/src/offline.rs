@@ -0,0 +1,280 @@
+//! Record query metadata to a directory and load it back without a database.
+//!
+//! Each query is stored as `query-<hash of the SQL>-<hash of the schema>.json`, holding
+//! the SQL text, the hash of the schema it was analysed against and its
+//! [`StatementInfo`]. The file `schema` holds the hash of the schema last recorded
+//! against, the one [`OfflineStore::load`] expects.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analyzer::Analyzer, cstr, error::ExplainerError, ffi::error::SqliteError, types::StatementInfo,
+};
+
+// 64-bit FNV-1a, stable across platforms and Rust versions unlike `DefaultHasher`
+fn hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// Hash of the schema of the database `analyzer` analyses against.
+pub fn schema_hash(analyzer: &Analyzer) -> Result<String, SqliteError> {
+    let schema = analyzer.connection().load_all(
+        cstr!("SELECT type, name, tbl_name, sql FROM sqlite_schema ORDER BY type, name"),
+        |row| -> Result<_, SqliteError> {
+            Ok((0..4)
+                .map(|i| row.column_text(i).to_string())
+                .collect::<Vec<_>>())
+        },
+    )?;
+    Ok(hash(schema.concat().join("\0").as_bytes()))
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct OfflineQuery {
+    pub sql: String,
+    pub schema_hash: String,
+    pub info: StatementInfo,
+}
+
+/// Why recorded query metadata couldn't be written or loaded.
+#[derive(Debug)]
+pub enum OfflineError {
+    /// Reading or writing a file of the store failed.
+    Io { path: PathBuf, error: io::Error },
+    /// A file of the store doesn't hold query metadata.
+    Json {
+        path: PathBuf,
+        error: serde_json::Error,
+    },
+    /// The SQL was never recorded, or changed since it was.
+    NotRecorded { dir: PathBuf, sql: String },
+    /// The file for the SQL was recorded for other SQL with the same hash.
+    OtherSql { path: PathBuf, sql: String },
+    /// The SQL was only recorded against other schemas than the one expected.
+    SchemaMismatch { sql: String, schema_hash: String },
+}
+
+impl Display for OfflineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            OfflineError::Io { path, error } => {
+                write!(f, "failed to access `{}`: {error}", path.display())
+            }
+            OfflineError::Json { path, error } => {
+                write!(f, "failed to parse `{}`: {error}", path.display())
+            }
+            OfflineError::NotRecorded { dir, sql } => write!(
+                f,
+                "no offline data for query in `{}`, the query is new or has changed since it \
+                 was recorded; record it again against a database:\n{sql}",
+                dir.display()
+            ),
+            OfflineError::OtherSql { path, sql } => write!(
+                f,
+                "offline data in `{}` was recorded for different SQL:\n{sql}",
+                path.display()
+            ),
+            OfflineError::SchemaMismatch { sql, schema_hash } => write!(
+                f,
+                "offline data for query was recorded against a different schema \
+                 (expected `{schema_hash}`); record it again:\n{sql}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OfflineError {}
+
+/// A directory of recorded query metadata.
+#[derive(Debug, Clone)]
+pub struct OfflineStore {
+    dir: PathBuf,
+}
+
+impl OfflineStore {
+    pub fn new(dir: impl Into<PathBuf>) -> OfflineStore {
+        OfflineStore { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn prefix(sql: &str) -> String {
+        format!("query-{}-", hash(sql.as_bytes()))
+    }
+
+    fn path(&self, sql: &str, schema_hash: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}{schema_hash}.json", Self::prefix(sql)))
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> Result<(), OfflineError> {
+        std::fs::create_dir_all(&self.dir)
+            .and_then(|()| std::fs::write(path, contents))
+            .map_err(|error| OfflineError::Io {
+                path: path.to_path_buf(),
+                error,
+            })
+    }
+
+    /// Analyse `sql` with `analyzer` and record the result.
+    pub fn record(
+        &self,
+        analyzer: &mut Analyzer,
+        sql: &str,
+    ) -> Result<StatementInfo, ExplainerError> {
+        let query = OfflineQuery {
+            sql: sql.to_string(),
+            schema_hash: schema_hash(analyzer)?,
+            info: analyzer.statement_info(sql)?,
+        };
+        let path = self.path(sql, &query.schema_hash);
+        let json = serde_json::to_string_pretty(&query).map_err(|error| OfflineError::Json {
+            path: path.clone(),
+            error,
+        })?;
+        self.write(&path, &json)?;
+        self.write(&self.dir.join("schema"), &query.schema_hash)?;
+        Ok(query.info)
+    }
+
+    /// Load the recorded metadata of `sql`, against the schema last recorded against.
+    ///
+    /// Fails if `sql` was never recorded, which includes SQL that changed since
+    /// it was recorded, or only against other schemas.
+    pub fn load(&self, sql: &str) -> Result<OfflineQuery, ExplainerError> {
+        let path = self.dir.join("schema");
+        let schema_hash = match std::fs::read_to_string(&path) {
+            Ok(schema_hash) => schema_hash,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(OfflineError::NotRecorded {
+                    dir: self.dir.clone(),
+                    sql: sql.to_string(),
+                }
+                .into())
+            }
+            Err(error) => return Err(OfflineError::Io { path, error }.into()),
+        };
+        self.load_query(sql, schema_hash.trim())
+    }
+
+    /// Like [`OfflineStore::load`], against the schema hashing to `schema_hash`.
+    pub fn load_for_schema(
+        &self,
+        sql: &str,
+        schema_hash: &str,
+    ) -> Result<StatementInfo, ExplainerError> {
+        Ok(self.load_query(sql, schema_hash)?.info)
+    }
+
+    fn load_query(&self, sql: &str, schema_hash: &str) -> Result<OfflineQuery, ExplainerError> {
+        let path = self.path(sql, schema_hash);
+        let data = match std::fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(self.missing(sql, schema_hash).into())
+            }
+            Err(error) => return Err(OfflineError::Io { path, error }.into()),
+        };
+        let query: OfflineQuery =
+            serde_json::from_str(&data).map_err(|error| OfflineError::Json {
+                path: path.clone(),
+                error,
+            })?;
+        if query.sql != sql {
+            return Err(OfflineError::OtherSql {
+                path,
+                sql: query.sql,
+            }
+            .into());
+        }
+        Ok(query)
+    }
+
+    /// Why `sql` has no file for `schema_hash`: it has one for another schema, or none.
+    fn missing(&self, sql: &str, schema_hash: &str) -> OfflineError {
+        let prefix = Self::prefix(sql);
+        let recorded = std::fs::read_dir(&self.dir).is_ok_and(|mut entries| {
+            entries.any(|entry| {
+                entry.is_ok_and(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            })
+        });
+        match recorded {
+            true => OfflineError::SchemaMismatch {
+                sql: sql.to_string(),
+                schema_hash: schema_hash.to_string(),
+            },
+            false => OfflineError::NotRecorded {
+                dir: self.dir.clone(),
+                sql: sql.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_load() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("explainer-offline-{}", std::process::id()));
+        let store = OfflineStore::new(&dir);
+        let sql = "SELECT name FROM users WHERE id = ?";
+
+        let mut analyzer =
+            Analyzer::with_schema(&["CREATE TABLE users(id INTEGER PRIMARY KEY, name TEXT)"])?;
+        let info = store.record(&mut analyzer, sql)?;
+        let schema = schema_hash(&analyzer)?;
+        drop(analyzer);
+
+        let query = store.load(sql)?;
+        assert_eq!(query.info, info);
+        assert_eq!(query.schema_hash, schema);
+        assert_eq!(store.load_for_schema(sql, &schema)?, info);
+
+        // recorded against another schema, the first record is kept
+        let mut other = Analyzer::with_schema(&["CREATE TABLE users(id INTEGER, name INTEGER)"])?;
+        let other_schema = schema_hash(&other)?;
+        assert_ne!(other_schema, schema);
+        let other_info = store.record(&mut other, sql)?;
+        assert_ne!(other_info, info);
+        assert_eq!(store.load(sql)?.info, other_info);
+        assert_eq!(store.load_for_schema(sql, &schema)?, info);
+
+        // only queries recorded against the schema last recorded against load
+        let mut analyzer = Analyzer::with_schema(&["CREATE TABLE users(id INTEGER)"])?;
+        store.record(&mut analyzer, "SELECT id FROM users")?;
+        let error = store.load(sql).unwrap_err();
+        assert!(matches!(
+            error,
+            ExplainerError::Offline(OfflineError::SchemaMismatch { .. })
+        ));
+
+        let error = store
+            .load("SELECT name FROM users WHERE id = ?1")
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("has changed since it was recorded"));
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        assert_eq!(hash(b""), "cbf29ce484222325");
+        assert_eq!(hash(b"a"), "af63dc4c8601ec8c");
+    }
+}
@@ -0,0 +1,339 @@
+//! Runtime support for the code generated by `explainer-macros`.
+
+use std::ffi::CStr;
+
+use crate::{
+    error::ExplainerError,
+    ffi::{connection::Connection, error::SqliteError, row::Row, statement::Statement},
+};
+
+/// A value that can be bound to a statement parameter.
+pub trait ToSql {
+    fn bind(&self, stmt: &mut Statement, index: usize) -> Result<(), SqliteError>;
+}
+
+/// A value that can be read from a result column.
+pub trait FromSql: Sized {
+    fn from_sql(row: &Row, index: usize) -> Self;
+}
+
+macro_rules! impl_sql {
+    ($ty:ty, $bind:ident, $column:ident) => {
+        impl ToSql for $ty {
+            fn bind(&self, stmt: &mut Statement, index: usize) -> Result<(), SqliteError> {
+                stmt.$bind(index, *self)
+            }
+        }
+
+        impl FromSql for $ty {
+            fn from_sql(row: &Row, index: usize) -> Self {
+                row.$column(index) as $ty
+            }
+        }
+    };
+}
+
+impl_sql!(i32, bind_int, column_int);
+impl_sql!(i64, bind_int64, column_int64);
+impl_sql!(f64, bind_double, column_double);
+
+impl ToSql for bool {
+    fn bind(&self, stmt: &mut Statement, index: usize) -> Result<(), SqliteError> {
+        stmt.bind_int(index, *self as i32)
+    }
+}
+
+impl FromSql for bool {
+    fn from_sql(row: &Row, index: usize) -> Self {
+        row.column_int(index) != 0
+    }
+}
+
+impl ToSql for str {
+    fn bind(&self, stmt: &mut Statement, index: usize) -> Result<(), SqliteError> {
+        stmt.bind_text(index, self)
+    }
+}
+
+impl ToSql for String {
+    fn bind(&self, stmt: &mut Statement, index: usize) -> Result<(), SqliteError> {
+        stmt.bind_text(index, self)
+    }
+}
+
+impl FromSql for String {
+    fn from_sql(row: &Row, index: usize) -> Self {
+        row.column_text(index).to_string()
+    }
+}
+
+impl ToSql for [u8] {
+    fn bind(&self, stmt: &mut Statement, index: usize) -> Result<(), SqliteError> {
+        stmt.bind_blob(index, self)
+    }
+}
+
+impl ToSql for Vec<u8> {
+    fn bind(&self, stmt: &mut Statement, index: usize) -> Result<(), SqliteError> {
+        stmt.bind_blob(index, self)
+    }
+}
+
+impl FromSql for Vec<u8> {
+    fn from_sql(row: &Row, index: usize) -> Self {
+        row.column_blob(index).to_vec()
+    }
+}
+
+impl<T: ToSql + ?Sized> ToSql for &T {
+    fn bind(&self, stmt: &mut Statement, index: usize) -> Result<(), SqliteError> {
+        (**self).bind(stmt, index)
+    }
+}
+
+impl<T: ToSql> ToSql for Option<T> {
+    fn bind(&self, stmt: &mut Statement, index: usize) -> Result<(), SqliteError> {
+        match self {
+            Some(value) => value.bind(stmt, index),
+            None => stmt.bind_null(index),
+        }
+    }
+}
+
+impl<T: FromSql> FromSql for Option<T> {
+    fn from_sql(row: &Row, index: usize) -> Self {
+        if row.column_is_null(index) {
+            None
+        } else {
+            Some(T::from_sql(row, index))
+        }
+    }
+}
+
+/// A value passed as a query argument, [`Arg::Value`] is what it binds once `Option`s and
+/// references are looked through.
+pub trait Arg {
+    type Value: ?Sized;
+}
+
+/// An argument that is never NULL.
+pub trait NotNullArg: Arg {}
+
+/// Arguments whose [`Arg::Value`] is `Self` can bind a parameter inferred to have the
+/// Rust type `T`.
+///
+/// ```
+/// use explainer::query::{not_null_arg, nullable_arg};
+///
+/// not_null_arg::<i64, _>(1);
+/// not_null_arg::<f64, _>(1i64);
+/// nullable_arg::<String, _>(Some("a"));
+/// nullable_arg::<String, _>(&"a".to_string());
+/// ```
+///
+/// A string can't bind an integer parameter:
+///
+/// ```compile_fail
+/// explainer::query::not_null_arg::<i64, _>("1");
+/// ```
+///
+/// Nor can an `Option` bind a parameter that can't be NULL:
+///
+/// ```compile_fail
+/// explainer::query::not_null_arg::<String, _>(Some("a"));
+/// ```
+#[diagnostic::on_unimplemented(message = "`{Self}` can't bind a parameter of type `{T}`")]
+pub trait Binds<T> {}
+
+macro_rules! impl_arg {
+    ($($ty:ty => $value:ty),* $(,)?) => {
+        $(
+            impl Arg for $ty {
+                type Value = $value;
+            }
+
+            impl NotNullArg for $ty {}
+        )*
+    };
+}
+
+impl_arg!(i32 => i32, i64 => i64, f64 => f64, bool => bool, str => str, String => str);
+impl_arg!([u8] => [u8], Vec<u8> => [u8]);
+
+impl<T: Arg + ?Sized> Arg for &T {
+    type Value = T::Value;
+}
+
+impl<T: NotNullArg + ?Sized> NotNullArg for &T {}
+
+impl<T: Arg> Arg for Option<T> {
+    type Value = T::Value;
+}
+
+macro_rules! impl_binds {
+    ($($value:ty => $($ty:ty),*;)*) => {
+        $($(impl Binds<$ty> for $value {})*)*
+    };
+}
+
+// integers bind any numeric parameter, SQLite converts them
+impl_binds! {
+    i32 => i32, i64, f64;
+    i64 => i32, i64, f64;
+    f64 => f64;
+    bool => bool;
+    str => String;
+    [u8] => Vec<u8>;
+}
+
+/// `arg`, failing to compile unless it can bind a nullable parameter of type `T`.
+#[doc(hidden)]
+pub fn nullable_arg<T, A: Arg>(arg: A) -> A
+where
+    A::Value: Binds<T>,
+{
+    arg
+}
+
+/// `arg`, failing to compile unless it can bind a NOT NULL parameter of type `T`.
+#[doc(hidden)]
+pub fn not_null_arg<T, A: NotNullArg>(arg: A) -> A
+where
+    A::Value: Binds<T>,
+{
+    arg
+}
+
+/// A statement with its arguments and a function to read its rows.
+pub struct Query<'a, R> {
+    sql: &'a CStr,
+    args: Vec<Box<dyn ToSql + 'a>>,
+    map: fn(&Row) -> R,
+}
+
+impl<'a, R> Query<'a, R> {
+    pub fn new(sql: &'a CStr, args: Vec<Box<dyn ToSql + 'a>>, map: fn(&Row) -> R) -> Self {
+        Query { sql, args, map }
+    }
+
+    pub fn sql(&self) -> &CStr {
+        self.sql
+    }
+
+    fn bind(&self, conn: &Connection) -> Result<Statement, SqliteError> {
+        let mut stmt = conn.prepare(self.sql)?;
+        for (i, arg) in self.args.iter().enumerate() {
+            arg.bind(&mut stmt, i + 1)?;
+        }
+        Ok(stmt)
+    }
+
+    fn run(&self, conn: &Connection, mut f: impl FnMut(R) -> bool) -> Result<(), SqliteError> {
+        let mut stmt = self.bind(conn)?;
+        while stmt.step()? {
+            if !f((self.map)(&Row::new(&stmt))) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the statement, returning the number of rows it changed.
+    pub fn execute(&self, conn: &Connection) -> Result<usize, SqliteError> {
+        let before = conn.total_changes();
+        let mut stmt = self.bind(conn)?;
+        while stmt.step()? {}
+        // the count of changes is left from an earlier statement when this one changes
+        // none, such as a SELECT or a CREATE TABLE
+        if stmt.read_only() || conn.total_changes() == before {
+            return Ok(0);
+        }
+        Ok(conn.changes())
+    }
+
+    pub fn fetch_all(&self, conn: &Connection) -> Result<Vec<R>, SqliteError> {
+        let mut rows = Vec::new();
+        self.run(conn, |row| {
+            rows.push(row);
+            true
+        })?;
+        Ok(rows)
+    }
+
+    pub fn fetch_optional(&self, conn: &Connection) -> Result<Option<R>, SqliteError> {
+        let mut first = None;
+        self.run(conn, |row| {
+            first = Some(row);
+            false
+        })?;
+        Ok(first)
+    }
+
+    /// Like [`Query::fetch_optional`], failing if the statement returns no rows.
+    pub fn fetch_one(&self, conn: &Connection) -> Result<R, ExplainerError> {
+        self.fetch_optional(conn)?.ok_or(ExplainerError::NoRows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cstr;
+
+    #[test]
+    fn test_query() -> anyhow::Result<()> {
+        let conn = Connection::establish(cstr!(":memory:"))?;
+        conn.exec(
+            cstr!("CREATE TABLE kv(key INTEGER NOT NULL, value TEXT, data BLOB)"),
+            None,
+        )?;
+
+        let insert = |key: i64, value: Option<&str>| {
+            Query::new(
+                cstr!("INSERT INTO kv VALUES (?, ?, ?)"),
+                vec![Box::new(key), Box::new(value), Box::new(vec![1u8, 2])],
+                |_| (),
+            )
+            .execute(&conn)
+        };
+        assert_eq!(insert(1, Some("one"))?, 1);
+        assert_eq!(insert(2, None)?, 1);
+        let execute = |sql| Query::new(sql, Vec::new(), |_| ()).execute(&conn);
+        assert_eq!(execute(cstr!("SELECT key FROM kv"))?, 0);
+        assert_eq!(execute(cstr!("CREATE TABLE other(a)"))?, 0);
+        assert_eq!(execute(cstr!("UPDATE kv SET value = value"))?, 2);
+        assert_eq!(execute(cstr!("DELETE FROM kv WHERE key = 3"))?, 0);
+
+        let select = Query::new(
+            cstr!("SELECT key, value, data FROM kv WHERE key >= ? ORDER BY key"),
+            vec![Box::new(1i32)],
+            |row| -> (i64, Option<String>, Vec<u8>) {
+                (
+                    FromSql::from_sql(row, 0),
+                    FromSql::from_sql(row, 1),
+                    FromSql::from_sql(row, 2),
+                )
+            },
+        );
+        assert_eq!(
+            select.fetch_all(&conn)?,
+            vec![
+                (1, Some("one".to_string()), vec![1, 2]),
+                (2, None, vec![1, 2]),
+            ]
+        );
+        assert_eq!(select.fetch_one(&conn)?.0, 1);
+
+        let missing = Query::new(
+            cstr!("SELECT key FROM kv WHERE key = ?"),
+            vec![Box::new(3i64)],
+            |row| -> i64 { FromSql::from_sql(row, 0) },
+        );
+        assert_eq!(missing.fetch_optional(&conn)?, None);
+        assert!(matches!(
+            missing.fetch_one(&conn),
+            Err(ExplainerError::NoRows)
+        ));
+        Ok(())
+    }
+}
@@ -1,9 +1,41 @@
-use std::str::FromStr;
+//! Types describing the inputs and outputs of a statement.
+//!
+//! With the `serde` feature these types implement `Serialize` and `Deserialize`.
+//! The serialized form is stable: fields keep the names they have in Rust, enum
+//! variants are written by name and `Option`s that are `None` are written as `null`.
+//! As JSON, a [`StatementInfo`] looks like
+//!
+//! ```json
+//! {
+//!   "read_only": true,
+//!   "input_length": 1,
+//!   "input_types": [{ "datatype": "BigInt", "nullable": false }],
+//!   "parameters": [{ "index": 1, "name": ":id", "repeated": false, "gap": false }],
+//!   "output_length": 1,
+//!   "output_types": [{ "datatype": "Text", "nullable": true }],
+//!   "columns": [
+//!     {
+//!       "name": "name",
+//!       "declared_type": "TEXT",
+//!       "origin": { "database": "main", "table": "users", "column": "name" }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! [`DataType`] is one of `"Null"`, `"Bool"`, `"Int"`, `"BigInt"`, `"Real"`, `"Text"`
+//! or `"Blob"`, and a [`Constraint`] is either `{ "Count": 2 }` or
+//! `{ "Types": [<ColumnType>, ...] }`. A [`ScriptStatement`] is
+//! `{ "span": { "start": 0, "end": 8 }, "info": <StatementInfo> }`.
+
+use std::{ops::Range, str::FromStr};
 
-use anyhow::bail;
 use libsqlite3_sys::{SQLITE_BLOB, SQLITE_FLOAT, SQLITE_INTEGER, SQLITE_TEXT};
 
+use crate::error::ExplainerError;
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DataType {
     Null,
     Bool,
@@ -15,12 +47,38 @@ pub enum DataType {
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ColumnType {
     pub datatype: DataType,
     pub nullable: Option<bool>,
 }
 
+impl DataType {
+    /// Rust type used for values of this type, `None` for [`DataType::Null`].
+    pub fn rust_type(&self) -> Option<&'static str> {
+        Some(match self {
+            DataType::Null => return None,
+            DataType::Bool => "bool",
+            DataType::Int => "i32",
+            DataType::BigInt => "i64",
+            DataType::Real => "f64",
+            DataType::Text => "String",
+            DataType::Blob => "Vec<u8>",
+        })
+    }
+}
+
 impl ColumnType {
+    /// Rust type used for values of this column, wrapped in `Option` unless the
+    /// column is known to be not null.
+    pub fn rust_type(&self) -> Option<String> {
+        let rust_type = self.datatype.rust_type()?;
+        Some(match self.nullable {
+            Some(false) => rust_type.to_string(),
+            _ => format!("Option<{rust_type}>"),
+        })
+    }
+
     pub fn from_type_code(type_code: i32) -> ColumnType {
         match type_code {
             SQLITE_INTEGER => Some(DataType::Int),
@@ -42,21 +100,68 @@ impl ColumnType {
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Constraint {
     Count(usize),
     Types(Vec<ColumnType>),
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParameterInfo {
+    /// 1-based index the parameter is bound at.
+    pub index: usize,
+    /// Name as written in the SQL (`?1`, `:id`, `@id`, `$id`), `None` for anonymous `?`.
+    pub name: Option<String>,
+    /// Whether the name is written more than once in the SQL.
+    pub repeated: bool,
+    /// No parameter is written for this index, it is skipped by a numbered parameter
+    /// such as `?5`, and is NULL unless bound.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub gap: bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColumnOrigin {
+    pub database: String,
+    pub table: String,
+    pub column: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColumnInfo {
+    /// Name of the result column, the alias given with `AS` if there is one.
+    pub name: String,
+    /// Declared type of the table column the result column comes from.
+    pub declared_type: Option<String>,
+    /// Table column the result column comes from, `None` for expressions.
+    pub origin: Option<ColumnOrigin>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StatementInfo {
     pub read_only: bool,
     pub input_length: usize,
+    pub input_types: Vec<Option<ColumnType>>,
+    pub parameters: Vec<ParameterInfo>,
     pub output_length: usize,
     pub output_types: Vec<Option<ColumnType>>,
+    pub columns: Vec<ColumnInfo>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScriptStatement {
+    /// Byte range of the statement in the script, without surrounding whitespace and leading comments.
+    pub span: Range<usize>,
+    pub info: StatementInfo,
 }
 
 impl FromStr for DataType {
-    type Err = anyhow::Error;
+    type Err = ExplainerError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let s = s.to_ascii_lowercase();
@@ -79,9 +184,7 @@ impl FromStr for DataType {
 
             _ if s.contains("real") || s.contains("floa") || s.contains("doub") => DataType::Real,
 
-            _ => {
-                bail!("unknown type: `{}`", s);
-            }
+            _ => return Err(ExplainerError::UnknownDeclaredType(s)),
         })
     }
 }
@@ -117,6 +220,105 @@ mod tests {
         assert_eq!(DataType::Bool, "BOOLEAN".parse()?);
         assert_eq!(DataType::Bool, "BOOL".parse()?);
 
+        assert!(matches!(
+            "DATETIME".parse::<DataType>(),
+            Err(ExplainerError::UnknownDeclaredType(name)) if name == "datetime"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rust_type() {
+        let column = |datatype, nullable| ColumnType { datatype, nullable };
+        assert_eq!(
+            column(DataType::BigInt, Some(false)).rust_type().as_deref(),
+            Some("i64")
+        );
+        assert_eq!(
+            column(DataType::Text, Some(true)).rust_type().as_deref(),
+            Some("Option<String>")
+        );
+        assert_eq!(
+            column(DataType::Blob, None).rust_type().as_deref(),
+            Some("Option<Vec<u8>>")
+        );
+        assert_eq!(column(DataType::Null, Some(true)).rust_type(), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_schema() -> anyhow::Result<()> {
+        let info = StatementInfo {
+            read_only: true,
+            input_length: 1,
+            input_types: vec![Some(ColumnType {
+                datatype: DataType::BigInt,
+                nullable: Some(false),
+            })],
+            parameters: vec![ParameterInfo {
+                index: 1,
+                name: Some(":id".to_string()),
+                repeated: false,
+                gap: false,
+            }],
+            output_length: 1,
+            output_types: vec![Some(ColumnType {
+                datatype: DataType::Text,
+                nullable: None,
+            })],
+            columns: vec![ColumnInfo {
+                name: "name".to_string(),
+                declared_type: Some("TEXT".to_string()),
+                origin: Some(ColumnOrigin {
+                    database: "main".to_string(),
+                    table: "users".to_string(),
+                    column: "name".to_string(),
+                }),
+            }],
+        };
+        let json = serde_json::to_string(&info)?;
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"read_only":true,"input_length":1,"#,
+                r#""input_types":[{"datatype":"BigInt","nullable":false}],"#,
+                r#""parameters":[{"index":1,"name":":id","repeated":false,"gap":false}],"#,
+                r#""output_length":1,"output_types":[{"datatype":"Text","nullable":null}],"#,
+                r#""columns":[{"name":"name","declared_type":"TEXT","#,
+                r#""origin":{"database":"main","table":"users","column":"name"}}]}"#
+            )
+        );
+        assert_eq!(serde_json::from_str::<StatementInfo>(&json)?, info);
+        // recorded before parameters had `gap`
+        let old = concat!(
+            r#"{"read_only":true,"input_length":1,"#,
+            r#""input_types":[{"datatype":"BigInt","nullable":false}],"#,
+            r#""parameters":[{"index":1,"name":":id","repeated":false}],"#,
+            r#""output_length":1,"output_types":[{"datatype":"Text","nullable":null}],"#,
+            r#""columns":[{"name":"name","declared_type":"TEXT","#,
+            r#""origin":{"database":"main","table":"users","column":"name"}}]}"#
+        );
+        assert_eq!(serde_json::from_str::<StatementInfo>(old)?, info);
+
+        let script = ScriptStatement { span: 0..8, info };
+        let json = serde_json::to_string(&script)?;
+        assert!(json.starts_with(r#"{"span":{"start":0,"end":8},"info":{"#));
+        assert_eq!(serde_json::from_str::<ScriptStatement>(&json)?, script);
+
+        let constraints = vec![
+            Constraint::Count(2),
+            Constraint::Types(vec![ColumnType {
+                datatype: DataType::Null,
+                nullable: Some(true),
+            }]),
+        ];
+        let json = serde_json::to_string(&constraints)?;
+        assert_eq!(
+            json,
+            r#"[{"Count":2},{"Types":[{"datatype":"Null","nullable":true}]}]"#
+        );
+        assert_eq!(serde_json::from_str::<Vec<Constraint>>(&json)?, constraints);
         Ok(())
     }
 }
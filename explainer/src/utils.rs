@@ -1,4 +1,10 @@
-use std::ffi::CStr;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ffi::{CStr, CString},
+};
+
+use crate::error::ExplainerError;
 
 /// Get static CStr from a string literal
 ///
@@ -38,3 +44,188 @@ pub const fn static_cstr(input: &'static str) -> &'static CStr {
     }
     unsafe { CStr::from_bytes_with_nul_unchecked(input.as_bytes()) }
 }
+
+/// `sql` as a C string for SQLite, failing at a NUL byte, where SQLite would stop reading.
+pub(crate) fn to_cstring(sql: &str) -> Result<CString, ExplainerError> {
+    CString::new(sql).map_err(|e| ExplainerError::InteriorNul(e.nul_position()))
+}
+
+fn is_id_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b >= 0x80
+}
+
+/// Length of the whitespace and comments at the start of `sql`.
+pub(crate) fn skip_whitespace_and_comments(sql: &str) -> usize {
+    let bytes = sql.as_bytes();
+    let mut i = 0;
+    loop {
+        if i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        } else if sql[i..].starts_with("--") {
+            i = sql[i..].find('\n').map_or(sql.len(), |end| i + end + 1);
+        } else if sql[i..].starts_with("/*") {
+            i = sql[i + 2..]
+                .find("*/")
+                .map_or(sql.len(), |end| i + 2 + end + 2);
+        } else {
+            return i;
+        }
+    }
+}
+
+/// The parameters written in `sql`, in order: `?`, `?NNN`, `:AAA`, `@AAA` and `$AAA`.
+///
+/// String literals, quoted identifiers and comments are skipped.
+fn parameters(sql: &str) -> Vec<&str> {
+    let bytes = sql.as_bytes();
+    let mut parameters = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i += 2;
+            }
+            quote @ (b'\'' | b'"' | b'`' | b'[') => {
+                let end = if quote == b'[' { b']' } else { quote };
+                i += 1;
+                while i < bytes.len() && bytes[i] != end {
+                    i += 1;
+                }
+                // doubled quotes are an escaped quote, scanning on handles them
+                i += 1;
+            }
+            b'?' => {
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                parameters.push(&sql[start..i]);
+            }
+            b':' | b'@' | b'$' => {
+                i += 1;
+                loop {
+                    if i < bytes.len() && is_id_char(bytes[i]) {
+                        i += 1;
+                    } else if bytes[start] == b'$' && sql[i..].starts_with("::") {
+                        i += 2;
+                    } else {
+                        break;
+                    }
+                }
+                if i - start > 1 {
+                    parameters.push(&sql[start..i]);
+                }
+            }
+            b if is_id_char(b) => {
+                while i < bytes.len() && is_id_char(bytes[i]) {
+                    i += 1;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    parameters
+}
+
+/// The name a parameter is counted under: `?01` and `?1` are both bound at index 1.
+pub(crate) fn parameter_key(name: &str) -> Cow<'_, str> {
+    match name.strip_prefix('?') {
+        Some(digits) if !digits.is_empty() => {
+            Cow::Owned(format!("?{}", digits.trim_start_matches('0')))
+        }
+        _ => Cow::Borrowed(name),
+    }
+}
+
+/// Count how many times each named parameter is written in `sql`, keyed by
+/// [`parameter_key`].
+///
+/// Anonymous `?` parameters are not counted, as each of them is a parameter of its own.
+pub(crate) fn parameter_occurrences(sql: &str) -> HashMap<Cow<'_, str>, usize> {
+    let mut occurrences = HashMap::new();
+    for parameter in parameters(sql) {
+        if parameter != "?" {
+            *occurrences.entry(parameter_key(parameter)).or_default() += 1;
+        }
+    }
+    occurrences
+}
+
+/// Indices of the anonymous `?` parameters in `sql`, numbered the way SQLite does:
+/// one past the largest index so far.
+pub(crate) fn anonymous_parameters(sql: &str) -> HashSet<usize> {
+    let mut anonymous = HashSet::new();
+    let mut named = HashMap::new();
+    let mut count = 0;
+    for parameter in parameters(sql) {
+        if parameter == "?" {
+            count += 1;
+            anonymous.insert(count);
+        } else if let Some(index) = parameter[1..]
+            .parse::<usize>()
+            .ok()
+            .filter(|_| parameter.starts_with('?'))
+        {
+            count = count.max(index);
+        } else if !named.contains_key(parameter) {
+            count += 1;
+            named.insert(parameter, count);
+        }
+    }
+    anonymous
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skip_whitespace_and_comments() {
+        assert_eq!(skip_whitespace_and_comments("SELECT 1"), 0);
+        assert_eq!(
+            skip_whitespace_and_comments(" -- a\n /* b */\tSELECT 1"),
+            15
+        );
+        assert_eq!(skip_whitespace_and_comments("  -- a"), 6);
+        assert_eq!(skip_whitespace_and_comments("/* a"), 4);
+    }
+
+    #[test]
+    fn test_parameter_occurrences() {
+        let occurrences = parameter_occurrences(
+            "SELECT ':a', \"@b\", [$c], a$b -- :a
+             /* :a */ FROM t WHERE x = :a AND y = ?2 OR z = ? OR w = :a OR v = @b OR u = ?2",
+        );
+        assert_eq!(occurrences.len(), 3);
+        assert_eq!(occurrences[":a"], 2);
+        assert_eq!(occurrences["?2"], 2);
+        assert_eq!(occurrences["@b"], 1);
+
+        let occurrences = parameter_occurrences("SELECT ?01, ?1, ?10");
+        assert_eq!(occurrences.len(), 2);
+        assert_eq!(occurrences[&parameter_key("?001")], 2);
+        assert_eq!(occurrences["?10"], 1);
+    }
+
+    #[test]
+    fn test_anonymous_parameters() {
+        let anonymous = |sql| {
+            let mut indices: Vec<_> = anonymous_parameters(sql).into_iter().collect();
+            indices.sort();
+            indices
+        };
+        assert_eq!(anonymous("SELECT ?, :a, ?, :a, ?5, ?"), vec![1, 3, 6]);
+        assert_eq!(anonymous("SELECT ?3, ?, '?'"), vec![4]);
+        assert_eq!(anonymous("SELECT ?2, ?1"), Vec::<usize>::new());
+    }
+}
@@ -0,0 +1,47 @@
+use std::ffi::CString;
+
+use explainer::{analyzer::Analyzer, codegen, cstr, ffi::connection::Connection};
+
+mod queries {
+    include!("codegen/queries.rs");
+}
+
+#[test]
+fn test_generated_module_is_current() -> anyhow::Result<()> {
+    let mut analyzer = Analyzer::with_schema(&[include_str!("schema.sql")])?;
+    let module = codegen::generate_files(&mut analyzer, &["tests/codegen/queries.sql"])?;
+    if module != include_str!("codegen/queries.rs") {
+        std::fs::write("tests/codegen/queries.rs", module)?;
+        panic!("tests/codegen/queries.rs was out of date and has been regenerated");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_generated_module() -> anyhow::Result<()> {
+    let conn = Connection::establish(cstr!(":memory:"))?;
+    conn.exec_batch(&CString::new(include_str!("schema.sql"))?)?;
+
+    assert_eq!(
+        queries::add_user(&conn, "alice", Some("alice@example.com"))?,
+        1
+    );
+    assert_eq!(queries::add_user(&conn, "bob", None)?, 1);
+    assert_eq!(queries::count_users(&conn)?.map(|row| row.n), Some(2));
+
+    let user = queries::get_user(&conn, 1)?.expect("alice was inserted");
+    let id: i64 = user.id;
+    assert_eq!((id, user.name.as_str()), (1, "alice"));
+    assert_eq!(user.email.as_deref(), Some("alice@example.com"));
+    assert_eq!(queries::get_user(&conn, 3)?, None);
+
+    let users = queries::find_users(&conn, 0)?;
+    assert_eq!(
+        users
+            .iter()
+            .map(|row| (row.id, row.name.as_str()))
+            .collect::<Vec<_>>(),
+        vec![(1, "alice"), (2, "bob")]
+    );
+    Ok(())
+}
@@ -0,0 +1,64 @@
+// Generated by explainer::codegen, do not edit.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetUserRow {
+    pub id: i64,
+    pub name: String,
+    pub email: Option<String>,
+}
+
+pub fn get_user(conn: &::explainer::ffi::connection::Connection, id: i64) -> Result<Option<GetUserRow>, ::explainer::ffi::error::SqliteError> {
+    ::explainer::query::Query::new(
+        ::explainer::cstr!("SELECT id, name, email FROM users WHERE id = :id;"),
+        vec![Box::new(id)],
+        |row| GetUserRow {
+            id: ::explainer::query::FromSql::from_sql(row, 0),
+            name: ::explainer::query::FromSql::from_sql(row, 1),
+            email: ::explainer::query::FromSql::from_sql(row, 2),
+        },
+    )
+    .fetch_optional(conn)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FindUsersRow {
+    pub id: i64,
+    pub name: String,
+}
+
+pub fn find_users(conn: &::explainer::ffi::connection::Connection, karma: i64) -> Result<Vec<FindUsersRow>, ::explainer::ffi::error::SqliteError> {
+    ::explainer::query::Query::new(
+        ::explainer::cstr!("SELECT id, name FROM users WHERE karma >= :karma ORDER BY id;"),
+        vec![Box::new(karma)],
+        |row| FindUsersRow {
+            id: ::explainer::query::FromSql::from_sql(row, 0),
+            name: ::explainer::query::FromSql::from_sql(row, 1),
+        },
+    )
+    .fetch_all(conn)
+}
+
+pub fn add_user(conn: &::explainer::ffi::connection::Connection, name: &str, email: Option<&str>) -> Result<usize, ::explainer::ffi::error::SqliteError> {
+    ::explainer::query::Query::new(
+        ::explainer::cstr!("INSERT INTO users(name, email) VALUES (:name, :email);"),
+        vec![Box::new(name), Box::new(email)],
+        |_| (),
+    )
+    .execute(conn)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountUsersRow {
+    pub n: i32,
+}
+
+pub fn count_users(conn: &::explainer::ffi::connection::Connection) -> Result<Option<CountUsersRow>, ::explainer::ffi::error::SqliteError> {
+    ::explainer::query::Query::new(
+        ::explainer::cstr!("SELECT count(*) AS n FROM users;"),
+        vec![],
+        |row| CountUsersRow {
+            n: ::explainer::query::FromSql::from_sql(row, 0),
+        },
+    )
+    .fetch_optional(conn)
+}
@@ -0,0 +1,11 @@
+-- name: get_user :one
+SELECT id, name, email FROM users WHERE id = :id;
+
+-- name: find_users
+SELECT id, name FROM users WHERE karma >= :karma ORDER BY id;
+
+-- name: add_user
+INSERT INTO users(name, email) VALUES (:name, :email);
+
+-- name: count_users :one
+SELECT count(*) AS n FROM users;